@@ -3,6 +3,9 @@ defmodule Centaurus.Core do
   This module holds the key function for working with QUIC sockets.
 
   See each function for details.
+
+  Timeouts are in milliseconds or :infinity. A nil timeout uses the timeout in the
+  socket's or stream's options, waiting forever when that is unset too.
   """
 
   alias Centaurus.Nif
@@ -40,7 +43,9 @@ defmodule Centaurus.Core do
   @doc """
   Opens a Quic socket to listen for incoming connections.
 
-  If port == 0, an available port number is assigned.
+  If port == 0, an available port number is assigned. The listener is owned by the
+  socket_pid, or by the caller when the config has none. Connections it accepts are owned
+  by the process that accepts them.
   """
   @spec listen(socket_config, stream_config) :: {:ok, Types.socket} | {:error, error}
   when socket_config: SocketConfig.t,
@@ -48,40 +53,168 @@ defmodule Centaurus.Core do
     error: Types.error
   def listen(socket_config, stream_config)
   def listen(socket_config, stream_config) do
-    Nif.listen(socket_config, stream_config)
+    with {:ok, socket_config} <- SocketConfig.resolve_fd(socket_config) do
+      Nif.listen(socket_config, stream_config)
+    end
   end
 
   @doc """
   Accepts a single incoming connection and returns a QuicSocket for the new connection.
+  The connection is owned by the calling process.
   """
   @spec accept(Types.socket, timeout) :: {:ok, Types.socket} | {:error, error}
-  when timeout: timeout,
+  when timeout: timeout | nil,
     error: Types.error
-  def accept(socket, timeout \\ :infinity)
+  def accept(socket, timeout \\ nil)
   def accept(socket, timeout) do
-    Nif.accept(socket, timeout)
+    Nif.accept(socket, timeout, nil, self())
+  end
+
+  @doc """
+  Accepts a connection without waiting for it. The caller is sent
+  `{:quic_accept, operation, result}`, where result is what accept/2 would return.
+  The accept can be abandoned with cancel/1.
+  """
+  @spec accept_async(Types.socket, timeout) :: {:ok, Types.operation} | {:error, error}
+  when timeout: timeout | nil,
+    error: Types.error
+  def accept_async(socket, timeout \\ nil)
+  def accept_async(socket, timeout) do
+    owner = self()
+    run_async(:quic_accept, &Nif.accept(socket, timeout, &1, owner))
   end
 
   @doc """
   Opens a connection to the specified server. Returns a QuicSocket on success.
+
+  The address is either an IP address or a hostname. Hostnames are resolved on the
+  runtime and each resolved address is tried, alternating between IPv6 and IPv4.
+  Only addresses the socket can reach are tried: an IPv4 socket skips IPv6 addresses, and an
+  IPv6 socket bound to "[::]" without ipv6_only reaches IPv4 addresses through mapped addresses.
+  When the socket config has no server_name the hostname is used as the server name.
   """
   @spec connect(socket_config, stream_config, port, address, opts, timeout) :: {:ok, Types.socket} | {:error, error}
   when port: Types.port_number,
-    address: Types.ip_addr,
+    address: Types.ip_addr | Types.hostname,
     opts: Types.socket_options,
-    timeout: timeout,
+    timeout: timeout | nil,
     socket_config: SocketConfig.t,
     stream_config: StreamConfig.t,
     error: Types.error
-  def connect(socket_config, stream_config, port, address, opts, timeout \\ :infinity)
+  def connect(socket_config, stream_config, port, address, opts, timeout \\ nil)
   def connect(socket_config, stream_config, port, address, _opts, timeout) do
-    address = :inet.ntoa(address) |> to_string
-    port = to_string(port)
-    Nif.connect(socket_config, stream_config, address <> ":" <> port, timeout)
+    with {:ok, socket_config} <- SocketConfig.resolve_fd(socket_config) do
+      Nif.connect(socket_config, stream_config, {host(address), port}, timeout)
+    end
   end
 
   @doc """
-  Opens a stream on the connection. Returns a QuicStream.
+  Opens a Quic socket that both listens for incoming connections and connects to
+  other peers from the same UDP port. Use accept/2 and connect_from/4 on it.
+
+  The socket config's certificates and private_key are presented to connecting
+  peers. trusted_certificates (Default of certificates) are trusted when connecting.
+  """
+  @spec peer(socket_config, stream_config) :: {:ok, Types.socket} | {:error, error}
+  when socket_config: SocketConfig.t,
+    stream_config: StreamConfig.t,
+    error: Types.error
+  def peer(socket_config, stream_config) do
+    with {:ok, socket_config} <- SocketConfig.resolve_fd(socket_config) do
+      Nif.peer(socket_config, stream_config)
+    end
+  end
+
+  @doc """
+  Opens a connection from a socket opened with peer/2. Returns a QuicSocket on success.
+  """
+  @spec connect_from(Types.socket, port, address, timeout) :: {:ok, Types.socket} | {:error, error}
+  when port: Types.port_number,
+    address: Types.ip_addr | Types.hostname,
+    timeout: timeout | nil,
+    error: Types.error
+  def connect_from(socket, port, address, timeout \\ nil)
+  def connect_from(socket, port, address, timeout) do
+    Nif.connect_from(socket, {host(address), port}, timeout)
+  end
+
+  defp host(address) when is_tuple(address), do: :inet.ntoa(address) |> to_string
+  defp host(address), do: to_string(address)
+
+  @doc """
+  Returns the id the runtime's messages use for the socket or stream, so they can be
+  matched against it. The id doesn't keep the socket or stream open.
+  """
+  @spec id(Types.socket | Types.stream) :: Types.id
+  def id(socket_or_stream) do
+    Nif.id(socket_or_stream)
+  end
+
+  @doc """
+  Returns how many connections the listener has accepted, and refused for each reason,
+  as %{accepted: 0, rate_limited: 0, denied: 0, at_capacity: 0}.
+  """
+  @spec admission_stats(Types.socket) :: {:ok, map} | {:error, error}
+  when error: Types.error
+  def admission_stats(socket) do
+    Nif.admission_stats(socket)
+  end
+
+  @doc """
+  Returns counters for every socket since the runtime started, as
+  %{connections_opened: 0, connections_closed: 0, handshake_failures: 0, bytes_read: 0, bytes_written: 0}.
+
+  The counters only go up, so they can be polled and reported as :telemetry measurements.
+  """
+  @spec metrics() :: {:ok, map} | {:error, Types.error}
+  def metrics() do
+    Nif.metrics()
+  end
+
+  @doc """
+  Returns the local address the socket is bound to, such as {{127, 0, 0, 1}, 4433}.
+  When the socket was bound to port 0 this is the port the system picked.
+  """
+  @spec local_address(Types.socket) :: {:ok, Types.socket_addr} | {:error, error}
+  when error: Types.error
+  def local_address(socket) do
+    Nif.local_address(socket)
+  end
+
+  @doc """
+  Moves the socket to a new local address, such as {{0, 0, 0, 0}, 4433} or "0.0.0.0:4433".
+
+  Open connections migrate to the new address. Clients rebind through the socket
+  returned by connect/6; servers rebind the listening socket.
+
+  When a peer moves to a new address the socket's owner is sent
+  `{:quic_peer_address, socket_id, address}`.
+  """
+  @spec rebind(Types.socket, String.t | Types.socket_addr) :: :ok | {:error, error}
+  when error: Types.error
+  def rebind(socket, bind_address) do
+    Nif.rebind(socket, bind_address)
+  end
+
+  @doc """
+  Accepts a stream opened by the peer. Returns a QuicStream owned by the caller.
+
+  Streams are only queued for accept_stream when the socket's stream acceptor is
+  passive, or when there is no process to send them to. Otherwise streams are sent
+  to their owner as `{:quic_stream, socket_id, stream, :bi | :uni, stream_id}`.
+  """
+  @spec accept_stream(Types.socket, direction, timeout) :: {:ok, Types.stream} | {:error, error}
+  when direction: :bi | :uni,
+    timeout: timeout | nil,
+    error: Types.error
+  def accept_stream(socket, direction, timeout \\ nil)
+  def accept_stream(socket, direction, timeout) do
+    Nif.accept_stream(socket, direction, timeout)
+  end
+
+  @doc """
+  Opens a stream on the connection. Returns a QuicStream owned by the stream config's
+  stream_pid, or by the caller when the config has none.
   Direction can either be :uni for unidirectional streams (write only) or
   :bi for bidirectional streams (read and write).
   """
@@ -92,8 +225,22 @@ defmodule Centaurus.Core do
     Nif.open_stream(socket, direction)
   end
 
+  @doc """
+  Splits a bidirectional stream into a send half and a receive half.
+
+  Each half is a stream of its own. Both start out owned by the stream's owner and
+  can be handed to different processes with controlling_process/2. Closing or
+  dropping one half leaves the other open. The original stream can no longer be used.
+  """
+  @spec split(Types.stream) :: {:ok, {Types.stream, Types.stream}} | {:error, error}
+  when error: Types.error
+  def split(stream) do
+    Nif.split(stream)
+  end
+
   @doc """
   Closes the stream with the given error code (Default of none).
+  The sending half is finished and the receiving half is stopped.
   Error codes are ignored for unidirectional streams. (Not Applicable)
 
   Valid error codes are:
@@ -105,32 +252,105 @@ defmodule Centaurus.Core do
     Nif.close_stream(stream, error_code)
   end
 
+  @doc """
+  Pings the peer every interval milliseconds while the connection is open, keeping an
+  otherwise idle connection from timing out. 0 stops the pings.
+
+  This is on top of the keep_alive option, which quinn fixes when the connection opens.
+  The pings are empty datagrams, so the peer must accept datagrams, as quinn peers do by
+  default. The first ping is sent right away and its error is returned if the peer can't
+  take them.
+  """
+  @spec set_keep_alive(Types.socket, non_neg_integer) :: :ok | {:error, error}
+  when error: Types.error
+  def set_keep_alive(socket, interval) do
+    Nif.set_keep_alive(socket, interval)
+  end
+
+  @doc """
+  Sets the stream's send priority. Data on streams with a higher priority is sent
+  before data on streams with a lower priority on the same connection.
+  The default priority is 0. The change is made after the writes already waiting on the
+  stream, and returns once it has been.
+  """
+  @spec set_priority(Types.stream, integer) :: :ok | {:error, error}
+  when error: Types.error
+  def set_priority(stream, priority) do
+    Nif.set_priority(stream, priority)
+  end
+
+  @doc """
+  Returns the stream's id, direction, who opened it (:host or :peer) and the socket
+  it belongs to. The socket is nil once it has been dropped.
+  """
+  @spec stream_info(Types.stream) :: {:ok, Types.stream_info} | {:error, error}
+  when error: Types.error
+  def stream_info(stream) do
+    Nif.stream_info(stream)
+  end
+
   @doc """
   Reads any available data from the stream.
-  Timeout defaults to infinity.
   """
   @spec read(Types.stream, amount, timeout) :: {:ok, data} | {:error, error}
   when amount: non_neg_integer(),
-    timeout: timeout,
+    timeout: timeout | nil,
     data: String.t,
     error: Types.error
-  def read(stream, amount, timeout \\ :infinity)
+  def read(stream, amount, timeout \\ nil)
   def read(stream, amount, timeout) do
-    Nif.read(stream, amount, timeout)
+    Nif.read(stream, amount, timeout, nil)
+  end
+
+  @doc """
+  Reads from the stream without waiting for the data. The caller is sent
+  `{:quic_read, operation, result}`, where result is what read/3 would return.
+  The read can be abandoned with cancel/1.
+  """
+  @spec read_async(Types.stream, amount, timeout) :: {:ok, Types.operation} | {:error, error}
+  when amount: non_neg_integer(),
+    timeout: timeout | nil,
+    error: Types.error
+  def read_async(stream, amount, timeout \\ nil)
+  def read_async(stream, amount, timeout) do
+    run_async(:quic_read, &Nif.read(stream, amount, timeout, &1))
+  end
+
+  @doc """
+  Cancels a call started with accept_async/2 or read_async/3. The call's result
+  becomes `{:error, :cancelled}` unless it had already finished, including while it is
+  still waiting behind other calls on the socket or stream.
+  """
+  @spec cancel(Types.operation) :: :ok | {:error, error}
+  when error: Types.error
+  def cancel(operation) do
+    Nif.cancel(operation)
+  end
+
+  # The NIF calls block, so async calls wait on them from their own process.
+  defp run_async(tag, call) do
+    with {:ok, operation} <- Nif.operation() do
+      caller = self()
+      {:ok, _pid} = Task.start(fn -> send(caller, {tag, operation, call.(operation)}) end)
+      {:ok, operation}
+    end
   end
 
   @doc """
   Writes the data to the stream.
   """
-  @spec write(Types.stream, data) :: :ok | {:error, error}
+  @spec write(Types.stream, data, timeout) :: :ok | {:error, error}
   when data: String.t,
+    timeout: timeout | nil,
     error: Types.error
-  def write(stream, data) do
-    Nif.write(stream, data)
+  def write(stream, data, timeout \\ nil)
+  def write(stream, data, timeout) do
+    Nif.write(stream, data, timeout)
   end
 
   @doc """
   Closes the socket with the given error code (Default of none).
+  Closing a listener closes every connection it accepted.
 
   Valid error codes are:
   none - No error, communication is complete.
@@ -142,4 +362,63 @@ defmodule Centaurus.Core do
   def close(socket, error_code, reason) do
     Nif.close(socket, error_code, reason)
   end
+
+  @doc """
+  Closes every connection on the endpoint of a socket returned by listen/2, connect/6 or peer/2.
+
+  The socket stays open, so wait_idle/2 can wait for the peers to be told.
+  """
+  @spec close_endpoint(Types.socket, error_code, reason) :: :ok | {:error, error}
+  when error_code: Types.error_code,
+    reason: String.t,
+    error: Types.error
+  def close_endpoint(socket, error_code \\ :none, reason \\ "")
+  def close_endpoint(socket, error_code, reason) do
+    Nif.close_endpoint(socket, error_code, reason)
+  end
+
+  @doc """
+  Waits until every connection on the socket's endpoint has finished closing, so the
+  peers have been sent their CONNECTION_CLOSE frames.
+  """
+  @spec wait_idle(Types.socket, timeout) :: :ok | {:error, error}
+  when timeout: timeout | nil,
+    error: Types.error
+  def wait_idle(socket, timeout \\ :infinity)
+  def wait_idle(socket, timeout) do
+    Nif.wait_idle(socket, timeout)
+  end
+
+  @doc """
+  Shuts down a listener once its connections are done.
+
+  The listener stops accepting new connections and tells each connection it accepted that
+  it is going away: the connection's owner is sent `{:quic_goaway, socket_id}`, and new streams
+  can no longer be opened on it. Each connection closes once its open streams are done, with
+  close when it is `{error_code, reason}` and the socket's close_code otherwise. Any still
+  open at the timeout are closed along with the listener.
+
+  Returns the number of connections that ended before the timeout.
+  """
+  @spec shutdown(Types.socket, timeout, close) :: {:ok, non_neg_integer} | {:error, error}
+  when timeout: timeout | nil,
+    close: {Types.error_code, String.t} | nil,
+    error: Types.error
+  def shutdown(socket, timeout \\ :infinity, close \\ nil)
+  def shutdown(socket, timeout, close) do
+    Nif.shutdown(socket, timeout, close)
+  end
+
+  @doc """
+  Assigns a new owner to the listener, connection or stream.
+
+  The owner is monitored; when it exits the socket or stream is closed. A listener stops
+  accepting when its owner exits, while the connections it accepted keep their own owners.
+  See Centaurus.Types.SocketConfig for who owns a socket or stream to begin with.
+  """
+  @spec controlling_process(Types.socket | Types.stream, pid) :: :ok | {:error, error}
+  when error: Types.error
+  def controlling_process(socket_or_stream, pid) do
+    Nif.controlling_process(socket_or_stream, pid)
+  end
 end
@@ -0,0 +1,47 @@
+defmodule Centaurus.Logger do
+  @moduledoc """
+  Hands the runtime's log events to Logger.
+
+  The runtime only logs once a handler is registered. Add this module to a supervision
+  tree with the most verbose level to forward:
+
+      children = [{Centaurus.Logger, level: :info}]
+
+  Levels are :debug, :info, :warn and :error. Events are logged with the endpoint,
+  connection and stream they happened in, such as
+  "endpoint{id=0 kind=Server}:connection{id=1}: closed reason=...".
+  """
+
+  use GenServer
+
+  require Logger
+
+  alias Centaurus.Nif
+
+  @spec start_link(keyword) :: GenServer.on_start
+  def start_link(opts \\ []) do
+    GenServer.start_link(__MODULE__, Keyword.get(opts, :level, :info), name: __MODULE__)
+  end
+
+  @impl true
+  def init(level) do
+    Process.flag(:trap_exit, true)
+    case Nif.set_log_handler(self(), level) do
+      :ok -> {:ok, level}
+      {:error, error} -> {:stop, error}
+    end
+  end
+
+  @impl true
+  def handle_info({:centaurus_log, level, message}, state) do
+    Logger.log(level, message)
+    {:noreply, state}
+  end
+
+  def handle_info(_msg, state), do: {:noreply, state}
+
+  @impl true
+  def terminate(_reason, level) do
+    Nif.set_log_handler(nil, level)
+  end
+end
@@ -7,21 +7,59 @@ defmodule Centaurus.Nif do
 
   def start(), do: err()
   
-  def accept(_socket, _timeout), do: err()
+  def accept(_socket, _timeout, _operation, _owner), do: err()
+
+  def admission_stats(_socket), do: err()
+
+  def id(_socket_or_stream), do: err()
+
+  def accept_stream(_socket, _stream_type, _timeout), do: err()
+
+  def cancel(_operation), do: err()
 
   def connect(_socket_config, _stream_config, _address, _timeout), do: err()
 
+  def connect_from(_socket, _address, _timeout), do: err()
+
   def close(_socket, _error_code, _reason), do: err()
 
+  def close_endpoint(_socket, _error_code, _reason), do: err()
+
   def close_stream(_stream, _error_code), do: err()
 
+  def controlling_process(_socket_or_stream, _pid), do: err()
+
   def listen(_socket_config, _stream_config), do: err()
 
+  def local_address(_socket), do: err()
+
+  def metrics(), do: err()
+
   def open_stream(_socket, _direction), do: err()
 
-  def read(_stream, _amount, _timeout), do: err()
+  def operation(), do: err()
+
+  def peer(_socket_config, _stream_config), do: err()
+
+  def read(_stream, _amount, _timeout, _operation), do: err()
+
+  def rebind(_socket, _address), do: err()
+
+  def set_keep_alive(_socket, _interval), do: err()
+
+  def set_log_handler(_pid, _level), do: err()
+
+  def set_priority(_stream, _priority), do: err()
+
+  def shutdown(_socket, _timeout, _close), do: err()
+
+  def split(_stream), do: err()
+
+  def stream_info(_stream), do: err()
+
+  def wait_idle(_socket, _timeout), do: err()
 
-  def write(_stream, _data), do: err()    
+  def write(_stream, _data, _timeout), do: err()    
 
   # The rest are for testing the translation between Elixir and Rust.
   def create_cert_and_key(_directory, _server_name), do: err()
@@ -41,4 +79,5 @@ defmodule Centaurus.Nif do
   def test_quic_socket(_socket), do: err()
   def test_quic_stream(_stream), do: err()  
   def test_pid(_pid), do: err()
+  def live_tasks(), do: err()
 end
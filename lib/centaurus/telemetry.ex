@@ -0,0 +1,62 @@
+defmodule Centaurus.Telemetry do
+  @moduledoc """
+  Emits the runtime's telemetry events with :telemetry.
+
+  Add this module to a supervision tree and set the socket config's telemetry_pid
+  to it, so handlers attached with :telemetry.attach/4 see the events:
+
+      children = [Centaurus.Telemetry]
+      socket_config = %SocketConfig{telemetry_pid: Centaurus.Telemetry.forwarder()}
+
+  The events are:
+
+    * `[:centaurus, :connection, :start]` - measurements `%{system_time, handshake_duration}`,
+      metadata `%{socket, peer, reason: nil}`
+    * `[:centaurus, :connection, :stop]` - measurements `%{duration}`,
+      metadata `%{socket, peer, reason}`
+    * `[:centaurus, :stream, :open]` - measurements `%{system_time}`,
+      metadata `%{id, direction, initiator, socket}`
+    * `[:centaurus, :stream, :close]` - measurements `%{duration}`, the same metadata as open
+
+  Durations are in :native time units. The socket is the connection's id (see
+  Centaurus.Core.id/1), nil once it has been dropped. Splitting
+  a stream closes it, and each half opens as a stream of its own.
+  """
+
+  use GenServer
+
+  @spec start_link(keyword) :: GenServer.on_start
+  def start_link(_opts \\ []) do
+    GenServer.start_link(__MODULE__, nil, name: __MODULE__)
+  end
+
+  @doc """
+  The pid to use as a socket config's telemetry_pid.
+  """
+  @spec forwarder() :: pid | nil
+  def forwarder() do
+    Process.whereis(__MODULE__)
+  end
+
+  @impl true
+  def init(state) do
+    {:ok, state}
+  end
+
+  @impl true
+  def handle_info({:centaurus_telemetry, event, measurements, metadata}, state) do
+    :telemetry.execute(event, measurements(event, measurements), metadata)
+    {:noreply, state}
+  end
+
+  # The runtime sends durations in microseconds.
+  defp measurements(event, measurements) do
+    measurements = Map.new(measurements, fn {name, micros} ->
+      {name, System.convert_time_unit(micros, :microsecond, :native)}
+    end)
+    case List.last(event) do
+      start when start in [:start, :open] -> Map.put(measurements, :system_time, System.system_time())
+      _ -> measurements
+    end
+  end
+end
@@ -14,6 +14,36 @@ defmodule Centaurus.Types do
   """
   @opaque stream :: reference
 
+  @typedoc """
+  A call started with accept_async or read_async, which can be cancelled.
+  """
+  @opaque operation :: reference
+
+  @typedoc """
+  Names a socket or stream in the runtime's messages. See Centaurus.Core.id/1.
+  """
+  @type id :: non_neg_integer
+
+  @typedoc """
+  The QUIC stream id. Unique within a connection.
+  """
+  @type stream_id :: non_neg_integer
+
+  @typedoc """
+  Information about a stream. See Centaurus.Core.stream_info/1.
+  """
+  @type stream_info :: %{
+    id: stream_id,
+    direction: :bi | :uni,
+    initiator: :host | :peer,
+    socket: socket | nil
+  }
+
+  @typedoc """
+  The hostname of the peer connection. Used when connecting.
+  """
+  @type hostname :: String.t | charlist
+
   @typedoc """
   The IP Address of the peer connection. Used when connecting.
   """
@@ -24,9 +54,15 @@ defmodule Centaurus.Types do
   """
   @type peer_port :: port_number
 
-  @type ip_addr :: :inet.ip_addresss
+  @type ip_addr :: :inet.ip_address
   @type port_number :: :inet.port_number
 
+  @typedoc """
+  A local or peer socket address. Addresses are returned in this form, and
+  "ip:port" strings are also accepted.
+  """
+  @type socket_addr :: {ip_addr, port_number}
+
   @typedoc """
   The errors that can occur either from the runtime or setting up a configuration.
   """
@@ -51,11 +87,40 @@ defmodule Centaurus.Types do
 
     The struct has the following components:
     bind_addr: The IP Address of the local connection
+    fd: An already open UDP socket to use instead of binding bind_addr, either a file
+    descriptor or an Erlang :socket handle. The socket is duplicated, so the caller keeps theirs.
+    Only supported on Unix, and listen or connect return an error for sockets that aren't UDP.
     bind_port: The port of the local connection
     server_name: The server name for the certificates
     server_key: The server's private key for the certificates
     certificates: The path of where the certificates are located
+    trusted_certificates: The path of the certificates trusted when connecting (Default of certificates)
     options: The connection's options (see options for details)
+    stream_acceptor: Who receives the streams opened by the peer (see StreamAcceptor)
+    admission: Which connections a listener accepts (see Admission)
+    retry: How a listener validates the addresses of new clients (see Retry)
+    reset_key: The path of a file holding the key stateless resets are derived from, 64 or more
+    random bytes. Reusing the key after a restart lets peers of the old endpoint be reset.
+    Without one a random key is used.
+    cid_length: The length in bytes of the connection IDs the socket issues, at most 20.
+    Connection IDs are random, so load balancers can only route on their length.
+    telemetry_pid: The process sent the socket's telemetry events (see Centaurus.Telemetry)
+
+    Every socket and stream has an owner, which is sent its messages and is monitored, so the
+    socket or stream closes when its owner exits (see Core.controlling_process/2). Calls that
+    are given a pid give what they return to it: Core.accept and Core.accept_stream give it
+    to their caller, as with :gen_tcp. Otherwise the pid in the config is the owner, the
+    socket_pid for listen, peer, connect and connect_from and the stream_pid for open_stream,
+    or the calling process when the config has none. Streams opened by the peer go to the
+    stream acceptor.
+
+    The owner is sent `{:quic_connected, socket_id}` once a connection is established and
+    `{:quic_closed, socket_id, reason}` when it ends, whether by a local close, the peer or an
+    idle timeout. A listener's owner is sent `{:quic_handshake_failed, address, reason}`
+    when an incoming connection fails its handshake. When a listener shuts down, the owners
+    of the connections it accepted are sent `{:quic_goaway, socket_id}` (see Core.shutdown/3).
+    Messages name sockets and streams by id (see Core.id/1) rather than holding them, so
+    a message left unread doesn't keep a connection open.
     """
     
     # TODO: Add certificates and server_name to enforced keys.
@@ -63,21 +128,37 @@ defmodule Centaurus.Types do
     defstruct [
       socket_pid: nil,
       bind_address: "0.0.0.0:0",
+      fd: nil,
       server_name: "",
       private_key: nil,
       options: [],
-      certificates: nil
+      certificates: nil,
+      trusted_certificates: nil,
+      stream_acceptor: nil,
+      admission: nil,
+      retry: nil,
+      reset_key: nil,
+      cid_length: nil,
+      telemetry_pid: nil
     ]
 
     alias Centaurus.Types
     
     @type t :: %__MODULE__{
       socket_pid: pid,
-      bind_address: String.t,
+      bind_address: String.t | Types.socket_addr,
+      fd: non_neg_integer | :socket.socket | nil,
       server_name: String.t,
       private_key: Path.t,
       options: Types.quic_options,
-      certificates: Path.t
+      certificates: Path.t,
+      trusted_certificates: Path.t | nil,
+      stream_acceptor: Types.StreamAcceptor.t | nil,
+      admission: Types.Admission.t | nil,
+      retry: Types.Retry.t | nil,
+      reset_key: Path.t | nil,
+      cid_length: 0..20 | nil,
+      telemetry_pid: pid | nil
     }
 
     @spec set_opts(__MODULE__.t, opts) :: {:ok, __MODULE__.t} | {:error, Types.error}
@@ -85,6 +166,19 @@ defmodule Centaurus.Types do
     def set_opts(socket_config, _opts) do
       {:ok, socket_config}
     end    
+
+    @doc """
+    Replaces an Erlang :socket handle in fd with its file descriptor.
+    """
+    @spec resolve_fd(__MODULE__.t) :: {:ok, __MODULE__.t} | {:error, Types.error}
+    def resolve_fd(%__MODULE__{fd: fd} = socket_config) when is_integer(fd) or is_nil(fd) do
+      {:ok, socket_config}
+    end
+    def resolve_fd(%__MODULE__{fd: socket} = socket_config) do
+      with {:ok, fd} <- :socket.getopt(socket, :otp, :fd) do
+        {:ok, %__MODULE__{socket_config | fd: fd}}
+      end
+    end
   end
 
   defmodule StreamConfig do
@@ -97,6 +191,7 @@ defmodule Centaurus.Types do
     socket_id: Ties the stream to the Quic socket
     direction: Either Bi-directional or Uni-directional access
     options: The stream's options (see options for details)
+    priority: The send priority set when the stream is opened (Default of 0)
     data: Data to read from the stream.
     """
 
@@ -106,13 +201,15 @@ defmodule Centaurus.Types do
     defstruct [
       stream_pid: nil,
       stream_type: :bi,
-      options: []
+      options: [],
+      priority: nil
     ]
 
     @type t :: %__MODULE__{
       stream_pid: pid,
       stream_type: :bi | :uni,
       options: Types.quic_options,
+      priority: integer | nil
     }
     
     @spec set_opts(__MODULE__.t, opts) :: {:ok, __MODULE__.t} | {:error, Types.error}
@@ -122,16 +219,144 @@ defmodule Centaurus.Types do
     end
   end
 
+  defmodule StreamAcceptor do
+    @moduledoc """
+    Decides who receives the streams opened by the peer.
+
+    mode: :active sends each stream to a process as
+    `{:quic_stream, socket_id, stream, :bi | :uni, stream_id}`. The pids for the stream type are
+    used in turn, or the socket's owner when the list is empty.
+    :passive queues the streams until they are taken with accept_stream.
+    bi: The pids receiving bidirectional streams
+    uni: The pids receiving unidirectional streams
+
+    Without a stream acceptor, streams are sent to the socket's owner.
+    """
+
+    defstruct [
+      mode: :active,
+      bi: [],
+      uni: []
+    ]
+
+    @type t :: %__MODULE__{
+      mode: :active | :passive,
+      bi: [pid],
+      uni: [pid]
+    }
+  end
+
+  defmodule Admission do
+    @moduledoc """
+    Limits the connections a listener accepts. Clients are checked as they arrive, before their
+    handshake, whether or not an accept is waiting. Refused connections are closed with the
+    application error code 2, since quinn only refuses with the transport's CONNECTION_REFUSED
+    through a limit it doesn't report.
+
+    max_connections: The most connections the listener handles at once, counting those in their
+    handshake, waiting for an accept and accepted but not yet closed
+    max_per_ip: The connections accepted from one address each rate_window
+    rate_window: The length of the rate limit window in milliseconds (Default of 1000)
+    allow: CIDR ranges, such as "10.0.0.0/8", to accept connections from. Empty allows all.
+    deny: CIDR ranges to refuse connections from. Checked before allow.
+    """
+
+    @enforce_keys []
+    defstruct [
+      max_connections: nil,
+      max_per_ip: nil,
+      rate_window: nil,
+      allow: [],
+      deny: []
+    ]
+
+    @type t :: %__MODULE__{
+      max_connections: non_neg_integer | nil,
+      max_per_ip: non_neg_integer | nil,
+      rate_window: non_neg_integer | nil,
+      allow: [String.t],
+      deny: [String.t]
+    }
+  end
+
+  defmodule Retry do
+    @moduledoc """
+    Address validation for a listener. With Retry the listener only starts a handshake once the
+    client has echoed a token, which proves the client owns its source address.
+
+    mode: :always sends a Retry to every new client, :never to none of them (Default of :never).
+    token_key: The path of a file holding the key that signs tokens, 64 or more random bytes.
+    Listeners sharing a key accept each other's tokens. Without one a random key is used.
+    token_lifetime: How long a token is valid in milliseconds (Default of 15000)
+    """
+
+    @enforce_keys []
+    defstruct [
+      mode: :never,
+      token_key: nil,
+      token_lifetime: nil
+    ]
+
+    @type t :: %__MODULE__{
+      mode: :always | :never,
+      token_key: Path.t | nil,
+      token_lifetime: non_neg_integer | nil
+    }
+  end
+
   defmodule Options do
     defstruct [
-      timeout: nil
+      timeout: nil,
+      close_code: nil,
+      queue_capacity: nil,
+      reuse_port: nil,
+      recv_buffer: nil,
+      send_buffer: nil,
+      ipv6_only: nil,
+      idle_timeout: nil,
+      keep_alive: nil
     ]
     
     @typedoc """
     The possible set of configuration options for Quic sockets and streams.
+
+    timeout: The milliseconds a call on the socket or stream waits when it is given a
+    nil timeout, such as accept, connect, read and write (Default of forever).
+
+    close_code: The error code used when the socket or stream is closed because
+    it was garbage collected (Default of 0).
+
+    queue_capacity: The number of calls that may be waiting or running on a socket or
+    stream before further calls return `{:error, :busy}` (Default of 64). A call keeps
+    its place until it returns. Once there is room again the owner is sent
+    `{:quic_ready, socket_id}` or `{:quic_ready, stream_id, quic_stream_id}`, with the ids
+    from Core.id/1 and the QUIC stream id.
+
+    reuse_port: Sets SO_REUSEPORT on the socket before it is bound.
+
+    recv_buffer, send_buffer: The socket's receive and send buffer sizes in bytes.
+
+    ipv6_only: Sets IPV6_V6ONLY on IPv6 sockets before they are bound.
+
+    idle_timeout: Milliseconds without traffic before a connection is closed, 0 for never
+    (Default of quinn's 10 seconds). The socket_pid is sent `{:quic_idle_timeout, socket_id}`
+    in place of `{:quic_closed, socket_id, reason}` when this happens.
+
+    keep_alive: Milliseconds between the packets sent to keep an otherwise idle connection
+    open (Default of none). Both are fixed when the connection is opened, so a client sets
+    them in the socket config it connects with. Core.set_keep_alive/2 adds pings to a
+    connection that is already open.
     """
     @type t :: %__MODULE__{
-      timeout: non_neg_integer() 
+      timeout: non_neg_integer() | nil,
+      close_code: non_neg_integer() | nil,
+      queue_capacity: non_neg_integer() | nil,
+      reuse_port: boolean | nil,
+      recv_buffer: non_neg_integer() | nil,
+      send_buffer: non_neg_integer() | nil,
+      ipv6_only: boolean | nil,
+      idle_timeout: non_neg_integer() | nil,
+      keep_alive: non_neg_integer() | nil
     }
   end
 end
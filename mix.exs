@@ -25,7 +25,8 @@ defmodule Centaurus.MixProject do
 
   defp deps do
     [
-      {:rustler, "~> 0.22.0-rc.0"}
+      {:rustler, "~> 0.22.0-rc.0"},
+      {:telemetry, "~> 0.4"}
     ]
   end
 end
@@ -0,0 +1,166 @@
+//! Decides which incoming connections a listener accepts.
+
+use crate::interface::types::{ Admission, AdmissionStats };
+
+use anyhow::{ Context, Result };
+
+use tokio::time::{ Instant };
+
+use std::{
+    collections::{ HashMap },
+    net::{ IpAddr },
+    sync::atomic::{ AtomicU64, Ordering },
+    time::{ Duration },
+};
+
+/// The listener's admission policy and the recent connections it has seen.
+#[derive(Debug, Default)]
+pub struct AdmissionControl {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+    max_connections: Option<usize>,
+    max_per_ip: Option<u32>,
+    window: Duration,
+    // The start of the current window and the connections seen in it, per source address.
+    recent: HashMap<IpAddr, (Instant, u32)>,
+}
+
+/// Why a connection was turned away.
+#[derive(Clone, Copy, Debug)]
+pub enum Refusal {
+    Denied,
+    AtCapacity,
+    RateLimited,
+}
+
+/// Counts of the listener's admission decisions. Shared with the listener's handle so they can
+/// be read while an accept is waiting.
+#[derive(Debug, Default)]
+pub struct AdmissionCounters {
+    accepted: AtomicU64,
+    rate_limited: AtomicU64,
+    denied: AtomicU64,
+    at_capacity: AtomicU64,
+}
+
+/// An IP address range such as "10.0.0.0/8" or "fd00::/8".
+#[derive(Clone, Copy, Debug)]
+struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl AdmissionControl {
+    pub fn new(admission: Option<&Admission>) -> Result<Self> {
+        let admission = match admission {
+            Some(admission) => admission,
+            None => return Ok(Self::default()),
+        };
+        Ok(AdmissionControl {
+            allow: parse_all(&admission.allow)?,
+            deny: parse_all(&admission.deny)?,
+            max_connections: admission.max_connections.map(|max_connections| max_connections as usize),
+            max_per_ip: admission.max_per_ip,
+            window: Duration::from_millis(admission.rate_window.unwrap_or(1000)),
+            recent: HashMap::new(),
+        })
+    }
+
+    /// Checks a new connection from the address against the deny and allow lists, then the
+    /// listener's open connections, then the per address rate limit.
+    pub fn admit(&mut self, address: IpAddr, open: usize, now: Instant) -> std::result::Result<(), Refusal> {
+        let address = unmap(address);
+        if self.deny.iter().any(|cidr| cidr.contains(address)) {
+            return Err(Refusal::Denied)
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|cidr| cidr.contains(address)) {
+            return Err(Refusal::Denied)
+        }
+        if self.max_connections.map_or(false, |max_connections| open >= max_connections) {
+            return Err(Refusal::AtCapacity)
+        }
+        let max_per_ip = match self.max_per_ip {
+            Some(max_per_ip) => max_per_ip,
+            None => return Ok(()),
+        };
+        let window = self.window;
+        self.recent.retain(|_, (start, _)| now.duration_since(*start) < window);
+        let (_, count) = self.recent.entry(address).or_insert((now, 0));
+        if *count >= max_per_ip {
+            return Err(Refusal::RateLimited)
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+impl AdmissionCounters {
+    pub fn record(&self, decision: std::result::Result<(), Refusal>) {
+        let counter = match decision {
+            Ok(()) => &self.accepted,
+            Err(Refusal::Denied) => &self.denied,
+            Err(Refusal::AtCapacity) => &self.at_capacity,
+            Err(Refusal::RateLimited) => &self.rate_limited,
+        };
+        counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn stats(&self) -> AdmissionStats {
+        AdmissionStats {
+            accepted: self.accepted.load(Ordering::SeqCst),
+            rate_limited: self.rate_limited.load(Ordering::SeqCst),
+            denied: self.denied.load(Ordering::SeqCst),
+            at_capacity: self.at_capacity.load(Ordering::SeqCst),
+        }
+    }
+}
+
+impl Cidr {
+    fn parse(raw: &str) -> Result<Self> {
+        let mut parts = raw.splitn(2, '/');
+        let network : IpAddr = parts.next()
+            .unwrap_or("")
+            .parse()
+            .with_context(|| format!("Invalid CIDR address: {}", raw))?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match parts.next() {
+            Some(prefix) => prefix.parse()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .with_context(|| format!("Invalid CIDR prefix: {}", raw))?,
+            None => max_prefix,
+        };
+        Ok(Cidr { network, prefix })
+    }
+
+    fn contains(&self, address: IpAddr) -> bool {
+        match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+// Dual stack listeners see IPv4 peers as ::ffff:a.b.c.d.
+fn unmap(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, _, _] => v6.to_ipv4().map_or(address, IpAddr::V4),
+            _ => address,
+        },
+        IpAddr::V4(_) => address,
+    }
+}
+
+fn parse_all(cidrs: &[String]) -> Result<Vec<Cidr>> {
+    cidrs.iter()
+        .map(|cidr| Cidr::parse(cidr))
+        .collect()
+}
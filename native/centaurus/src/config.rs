@@ -1,5 +1,6 @@
 /// Provides a trait for setting up a connection.
 use crate::interface::types::{ SocketRef, StreamRef };
+use rustler::{ LocalPid };
 use tokio::sync::{ RwLock };
 use std::sync::Arc;
 
@@ -9,3 +10,44 @@ pub struct Configs {
     pub stream_config: Arc<RwLock<StreamRef>>,
 }
 
+impl Configs {
+    /// Gives a new connection its own socket config so it can change owners without
+    /// affecting the listener it was accepted from.
+    pub async fn fork_socket(&self) -> Configs {
+        let socket_config = self.socket_config.read().await.clone();
+        Configs {
+            socket_config: Arc::new(RwLock::new(socket_config)),
+            stream_config: self.stream_config.clone(),
+        }
+    }
+
+    /// Gives a new stream its own stream config. The socket config stays shared with the
+    /// connection the stream belongs to.
+    pub async fn fork_stream(&self) -> Configs {
+        let stream_config = self.stream_config.read().await.clone();
+        Configs {
+            socket_config: self.socket_config.clone(),
+            stream_config: Arc::new(RwLock::new(stream_config)),
+        }
+    }
+
+    /// Forks the socket config for a connection that belongs to the given process.
+    pub async fn fork_socket_for(&self, owner: LocalPid) -> Configs {
+        let configs = self.fork_socket().await;
+        {
+            let mut config = configs.socket_config.write().await;
+            *config = config.with_owner(owner);
+        }
+        configs
+    }
+
+    /// Forks the stream config for a stream that belongs to the given process.
+    pub async fn fork_stream_for(&self, owner: LocalPid) -> Configs {
+        let configs = self.fork_stream().await;
+        {
+            let mut config = configs.stream_config.write().await;
+            *config = config.with_owner(owner);
+        }
+        configs
+    }
+}
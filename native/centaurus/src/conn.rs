@@ -1,22 +1,26 @@
 /// Creates the client and server configurations from the supplied Config data and
 /// initializes the handler for the connection.
 
-use crate::error::{ ApplicationError };
+use crate::admission::{ AdmissionCounters };
+use crate::error::{ ApplicationError, Busy };
 use crate::config::{ Configs };
 use crate::interface::{
-    types::{ SocketType, SocketRef, StreamRef },
+    types::{ AdmissionStats, PeerAddr, RetryMode, SocketType, SocketRef, StreamInfo, StreamRef, StreamType, Timeout },
 };
 use crate::runtime;
-use crate::runtime::{ Event, NewSocketEvent, SocketEvent, StreamEvent };
+use crate::runtime::{ Cancel, Event, NewSocketEvent, SocketEvent, StreamEvent };
 
 use anyhow::{ Context, Result };
 
 use either::{ Either };
 
+use rustler::{ LocalPid };
+
 use quinn::{
+    ClientConfig,
     ClientConfigBuilder,
-    Endpoint,
     EndpointBuilder,
+    EndpointConfig,
     ServerConfig,
     ServerConfigBuilder,
     TransportConfig
@@ -26,15 +30,22 @@ use tokio::sync::{
     Mutex,
     RwLock,
     mpsc::{
+        channel as queue_channel,
+        error::{ TrySendError },
+        Receiver as QueueReceiver,
+        Sender as QueueSender,
         UnboundedSender as AsyncSender,
     },
+    oneshot,
 };
 
 use std::{
     default::{ Default },
+    fmt,
     net::{ SocketAddr },
     ops::{ Deref },
     sync::{ Arc },
+    sync::atomic::{ AtomicBool, AtomicU64, AtomicUsize, Ordering },
     sync::mpsc::{ channel },
     time::{ Duration },
 };
@@ -46,12 +57,285 @@ pub fn start_runtime() {
     };
 }
 
-pub struct NewSocket(pub AsyncSender<NewSocketEvent>);
-pub struct Socket(pub AsyncSender<SocketEvent>);
-pub struct Stream(pub AsyncSender<StreamEvent>);
+static NEXT_HANDLE_ID : AtomicU64 = AtomicU64::new(0);
+
+/// The runtime's side of a handle. Once the last reference to the handle is dropped the
+/// runtime is told to clean up the task.
+pub struct Handle<T: ControlEvent> {
+    // Names the socket or stream in the runtime's messages, which don't hold the handle itself.
+    id: u64,
+    sender: QueueSender<Queued<T>>,
+    // Used by the places in the queue to tell the runtime they were given back.
+    control: AsyncSender<T>,
+    capacity: Arc<Capacity>,
+}
+
+/// The events the runtime sends itself. They don't take a place in the queue.
+pub trait ControlEvent: Sized {
+    /// Sent when the handle is dropped.
+    fn dropped() -> Self;
+    /// Sent when a place is given back to a queue that turned an event away.
+    fn ready() -> Self;
+}
+
+impl ControlEvent for NewSocketEvent {
+    fn dropped() -> Self {
+        NewSocketEvent::Dropped
+    }
+
+    fn ready() -> Self {
+        NewSocketEvent::Ready
+    }
+}
+
+impl ControlEvent for SocketEvent {
+    fn dropped() -> Self {
+        SocketEvent::Dropped
+    }
+
+    fn ready() -> Self {
+        SocketEvent::Ready
+    }
+}
+
+impl ControlEvent for StreamEvent {
+    fn dropped() -> Self {
+        StreamEvent::Dropped
+    }
+
+    fn ready() -> Self {
+        StreamEvent::Ready
+    }
+}
+
+/// An event waiting in a socket or stream's queue, with the place it takes.
+#[derive(Debug)]
+pub struct Queued<T: ControlEvent> {
+    pub event: T,
+    pub slot: Option<Slot<T>>,
+}
+
+/// A place in a socket or stream's queue. It is held until the event's operation has finished,
+/// not just until the event is taken off the queue.
+pub struct Slot<T: ControlEvent> {
+    capacity: Arc<Capacity>,
+    control: AsyncSender<T>,
+}
+
+impl<T: ControlEvent> Drop for Slot<T> {
+    fn drop(&mut self) {
+        if self.capacity.give() {
+            self.control.send(T::ready()).ok();
+        }
+    }
+}
+
+impl<T: ControlEvent> fmt::Debug for Slot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Queue Slot.")
+    }
+}
+
+/// Creates the queue for a socket or stream. The queue has room for one more event than its
+/// capacity, kept for the event sent when the handle is dropped.
+pub fn queue<T: ControlEvent>(capacity: usize) -> (QueueSender<Queued<T>>, QueueReceiver<Queued<T>>) {
+    queue_channel(capacity.saturating_add(1))
+}
+
+impl<T: ControlEvent> Handle<T> {
+    pub fn new(sender: QueueSender<Queued<T>>, control: AsyncSender<T>, capacity: Arc<Capacity>) -> Self {
+        Handle {
+            id: NEXT_HANDLE_ID.fetch_add(1, Ordering::SeqCst),
+            sender,
+            control,
+            capacity,
+        }
+    }
+
+    /// The id the runtime's messages use for the socket or stream.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Queues the event on the runtime. Fails with Busy when the queue is full.
+    pub fn send(&self, event: T) -> Result<()> {
+        if !self.capacity.take() {
+            return Err(Busy.into());
+        }
+        let slot = Slot {
+            capacity: self.capacity.clone(),
+            control: self.control.clone(),
+        };
+        self.sender
+            .clone()
+            .try_send(Queued { event, slot: Some(slot) })
+            .map_err(|err| match err {
+                TrySendError::Full(_) => Busy.into(),
+                TrySendError::Closed(_) => anyhow::anyhow!("Runtime task closed."),
+            })
+    }
+}
+
+impl<T: ControlEvent> Handle<T> {
+    /// Sends the event past the queue, so it's never turned away as busy.
+    pub fn control(&self, event: T) -> Result<()> {
+        self.control
+            .send(event)
+            .map_err(|_err| anyhow::anyhow!("Runtime task closed."))
+    }
+}
+
+impl<T: ControlEvent> Drop for Handle<T> {
+    fn drop(&mut self) {
+        // Goes through the queue so it's handled after the events sent before it.
+        self.sender.try_send(Queued { event: T::dropped(), slot: None }).ok();
+    }
+}
+
+/// Limits the number of events waiting on a socket or stream.
+#[derive(Debug)]
+pub struct Capacity {
+    available: AtomicUsize,
+    // Set when an event was turned away, so the owner can be told once there is room again.
+    busy: AtomicBool,
+}
+
+impl Capacity {
+    pub fn new(capacity: usize) -> Self {
+        Capacity {
+            available: AtomicUsize::new(capacity),
+            busy: AtomicBool::new(false),
+        }
+    }
+
+    /// Takes a place in the queue. Returns false if the queue is full.
+    pub fn take(&self) -> bool {
+        let taken = self.available
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |available| available.checked_sub(1))
+            .is_ok();
+        if !taken {
+            self.busy.store(true, Ordering::SeqCst);
+        }
+        taken
+    }
+
+    /// Gives back a place in the queue. Returns true if an event was turned away since the last
+    /// time there was room.
+    pub fn give(&self) -> bool {
+        self.available.fetch_add(1, Ordering::SeqCst);
+        self.busy.swap(false, Ordering::SeqCst)
+    }
+}
+
+// The longest connection ID QUIC allows.
+const MAX_CID_LENGTH : usize = 20;
+
+fn endpoint_config(socket_config: &SocketRef) -> Result<EndpointConfig> {
+    let mut endpoint_config = EndpointConfig::default();
+    if let Some(cid_length) = socket_config.cid_length {
+        if cid_length > MAX_CID_LENGTH {
+            return Err(anyhow::anyhow!("Connection ID length must be at most {}.", MAX_CID_LENGTH))
+        }
+        endpoint_config.local_cid_len = cid_length;
+    }
+    if let Some(reset_key) = socket_config.reset_key.as_ref() {
+        endpoint_config.reset_key(&reset_key.as_bytes()?)
+            .context("Invalid Reset Key.")?;
+    }
+    Ok(endpoint_config)
+}
+
+// quinn fixes a connection's idle timeout and keep-alive when the connection is opened.
+fn transport_config(socket_config: &SocketRef) -> Result<TransportConfig> {
+    let mut transport_config = TransportConfig::default();
+    if let Some(idle_timeout) = socket_config.options.idle_timeout {
+        let idle_timeout = Some(idle_timeout)
+            .filter(|idle_timeout| *idle_timeout > 0)
+            .map(Duration::from_millis);
+        transport_config.idle_timeout(idle_timeout)
+            .context("Invalid Idle Timeout.")?;
+    }
+    if let Some(keep_alive) = socket_config.options.keep_alive {
+        transport_config.keep_alive_interval(Some(Duration::from_millis(keep_alive)));
+    }
+    Ok(transport_config)
+}
+
+fn client_config(socket_config: &SocketRef) -> Result<ClientConfig> {
+    let certs = socket_config.certs()?;
+    let mut client = ClientConfigBuilder::default();
+    client.add_certificate_authority(certs)?;
+    let mut client_config = client.build();
+    client_config.transport = Arc::new(transport_config(socket_config)?);
+    Ok(client_config)
+}
+
+fn server_config(socket_config: &SocketRef) -> Result<ServerConfig> {
+    let private_key = socket_config.private_key()?;
+    let mut transport_config = transport_config(socket_config)?;
+    transport_config.stream_window_uni(0);
+    let mut server_config = ServerConfig::default();
+    server_config.transport = Arc::new(transport_config);
+    if let Some(retry) = socket_config.retry.as_ref() {
+        server_config.use_stateless_retry = retry.mode == RetryMode::Always;
+        if let Some(token_lifetime) = retry.token_lifetime {
+            // quinn counts the lifetime in microseconds.
+            server_config.retry_token_lifetime = token_lifetime.saturating_mul(1000);
+        }
+        if let Some(token_key) = retry.token_key.as_ref() {
+            server_config.token_key(&token_key.as_bytes()?)
+                .context("Invalid Token Key.")?;
+        }
+    }
+    let cert_chain = socket_config.cert_chain()?;
+    let mut server = ServerConfigBuilder::new(server_config);
+    server.certificate(cert_chain, private_key)?;
+    Ok(server.build())
+}
+
+/// A call that can be cancelled from another process. Cancelling it before the call reaches
+/// the runtime cancels the call as soon as it does.
+pub struct Operation {
+    cancel: Mutex<Option<oneshot::Sender<()>>>,
+    cancelled: Mutex<Option<Cancel>>,
+}
+
+impl Operation {
+    pub fn new() -> Self {
+        let (cancel, cancelled) = oneshot::channel();
+        Operation {
+            cancel: Mutex::new(Some(cancel)),
+            cancelled: Mutex::new(Some(cancelled)),
+        }
+    }
+
+    pub fn cancel(&self) -> Result<()> {
+        self.cancel
+            .try_lock()
+            .ok()
+            .and_then(|mut cancel| cancel.take())
+            .ok_or_else(|| anyhow::anyhow!("Operation already cancelled."))?
+            .send(())
+            .ok();
+        Ok(())
+    }
+
+    /// Hands out the signal the runtime waits on. An operation can only be used for one call.
+    pub fn start(&self) -> Result<Cancel> {
+        self.cancelled
+            .try_lock()
+            .ok()
+            .and_then(|mut cancelled| cancelled.take())
+            .ok_or_else(|| anyhow::anyhow!("Operation already used."))
+    }
+}
+
+pub struct NewSocket(pub Arc<Handle<NewSocketEvent>>, pub Arc<AdmissionCounters>);
+pub struct Socket(pub Arc<Handle<SocketEvent>>);
+pub struct Stream(pub Arc<Handle<StreamEvent>>);
 
 impl Deref for NewSocket {
-    type Target = AsyncSender<NewSocketEvent>;
+    type Target = Handle<NewSocketEvent>;
     
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -59,7 +343,7 @@ impl Deref for NewSocket {
 }
 
 impl Deref for Socket {
-    type Target = AsyncSender<SocketEvent>;
+    type Target = Handle<SocketEvent>;
     
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -67,7 +351,7 @@ impl Deref for Socket {
 }
 
 impl Deref for Stream {
-    type Target = AsyncSender<StreamEvent>;
+    type Target = Handle<StreamEvent>;
     
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -77,28 +361,22 @@ impl Deref for Stream {
 impl NewSocket {
     pub fn new(conn_type: SocketType, socket_config: SocketRef, stream_config: StreamRef) -> Result<Self> {
         let (sender, receiver) = channel();
-        let mut endpoint = Endpoint::builder();
+        let mut endpoint = EndpointBuilder::new(endpoint_config(&socket_config)?);
         let socket_handle = runtime::handle()?
             .left()
             .ok_or_else(|| anyhow::anyhow!("Runtime not started."))?;
         let state : EndpointBuilder = match conn_type {
             SocketType::Client => {
-                let certs = socket_config.certs()?;
-                let mut client = ClientConfigBuilder::default();
-                client.add_certificate_authority(certs)?;
-                endpoint.default_client_config(client.build());
+                endpoint.default_client_config(client_config(&socket_config)?);
                 endpoint
             },
             SocketType::Server => {
-                let private_key = socket_config.private_key()?;
-                let mut transport_config = TransportConfig::default();
-                transport_config.stream_window_uni(0);
-                let mut server_config = ServerConfig::default();
-                server_config.transport = Arc::new(transport_config);
-                let cert_chain = socket_config.cert_chain()?;
-                let mut server = ServerConfigBuilder::new(server_config);
-                server.certificate(cert_chain, private_key)?;
-                endpoint.listen(server.build());
+                endpoint.listen(server_config(&socket_config)?);
+                endpoint
+            },
+            SocketType::Peer => {
+                endpoint.default_client_config(client_config(&socket_config)?);
+                endpoint.listen(server_config(&socket_config)?);
                 endpoint
             },
         };
@@ -113,17 +391,15 @@ impl NewSocket {
             .context("Error receiving data from runtime.")?
     }
     
-    pub fn accept(&self, timeout: Option<u64>) -> Result<Socket> {
-        let timeout = timeout.map(|time| Duration::from_millis(time));
+    pub fn accept(&self, pid: LocalPid, timeout: Timeout, cancel: Option<Cancel>) -> Result<Socket> {
         let (sender, receiver) = channel();
         let response_channel = Mutex::new(sender);
-        self.send(NewSocketEvent::Accept(response_channel, timeout))?;
+        self.send(NewSocketEvent::Accept(response_channel, pid, timeout, cancel))?;
         receiver.recv()
             .context("Error receiving data from runtime.")?
     }
 
-    pub fn connect(&self, address: SocketAddr, timeout: Option<u64>) -> Result<Socket> {
-        let timeout = timeout.map(|time| Duration::from_millis(time));
+    pub fn connect(&self, address: PeerAddr, timeout: Timeout) -> Result<Socket> {
         let (sender, receiver) = channel();
         let response_channel = Mutex::new(sender);
         let event = NewSocketEvent::Connect(response_channel, address, timeout);
@@ -131,28 +407,82 @@ impl NewSocket {
         receiver.recv()
             .context("Error receiving data from runtime.")?
     }
+
+    pub fn controlling_process(&self, pid: LocalPid) -> Result<()> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = NewSocketEvent::ControllingProcess(response_channel, pid);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
     
+    /// Read straight from the counters, so this doesn't wait behind a pending accept.
+    pub fn admission_stats(&self) -> AdmissionStats {
+        self.1.stats()
+    }
+
+    pub fn local_address(&self) -> Result<SocketAddr> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = NewSocketEvent::LocalAddress(response_channel);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
+
+    pub fn rebind(&self, address: SocketAddr) -> Result<()> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = NewSocketEvent::Rebind(response_channel, address);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
+
     pub fn close(&self, error_code: ApplicationError, reason: Option<String>) -> Result<()> {
         let event = NewSocketEvent::Close(error_code, reason);
         self.send(event)?;
         Ok(())
     }
+
+    pub fn close_endpoint(&self, error_code: ApplicationError, reason: Option<String>) -> Result<()> {
+        let event = NewSocketEvent::CloseEndpoint(error_code, reason);
+        self.send(event)?;
+        Ok(())
+    }
+
+    pub fn wait_idle(&self, timeout: Timeout) -> Result<()> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        self.send(NewSocketEvent::WaitIdle(response_channel, timeout))?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
+
+    pub fn shutdown(&self, timeout: Timeout, close: Option<(ApplicationError, Option<String>)>) -> Result<usize> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        self.send(NewSocketEvent::Shutdown(response_channel, timeout, close))?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
 }
 
 impl Socket {
-    pub fn new_uni_stream(&self) -> Result<Stream> {
+    pub fn new_uni_stream(&self, pid: LocalPid) -> Result<Stream> {
         let (sender, receiver) = channel();
         let response_channel = Mutex::new(sender);
-        let event = SocketEvent::OpenUniStream(response_channel);
+        let event = SocketEvent::OpenUniStream(response_channel, pid);
         self.send(event)?;
         receiver.recv()
             .context("Error receiving data from runtime.")?
     }
     
-    pub fn new_bi_stream(&self) -> Result<Stream> {
+    pub fn new_bi_stream(&self, pid: LocalPid) -> Result<Stream> {
         let (sender, receiver) = channel();
         let response_channel = Mutex::new(sender);
-        let event = SocketEvent::OpenBiStream(response_channel);
+        let event = SocketEvent::OpenBiStream(response_channel, pid);
         self.send(event)?;
         receiver.recv()
             .context("Error receiving data from runtime.")?
@@ -163,50 +493,118 @@ impl Socket {
         self.send(event)?;
         Ok(())
     }
+
+    pub fn accept_stream(&self, pid: LocalPid, stream_type: StreamType, timeout: Timeout) -> Result<Stream> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = SocketEvent::AcceptStream(response_channel, pid, stream_type, timeout);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
+
+    pub fn local_address(&self) -> Result<SocketAddr> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = SocketEvent::LocalAddress(response_channel);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
+
+    pub fn rebind(&self, address: SocketAddr) -> Result<()> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = SocketEvent::Rebind(response_channel, address);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
+
+    pub fn set_keep_alive(&self, interval: u64) -> Result<()> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = SocketEvent::SetKeepAlive(response_channel, interval);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
+
+    pub fn controlling_process(&self, pid: LocalPid) -> Result<()> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = SocketEvent::ControllingProcess(response_channel, pid);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
+    }
 }
 
 impl Stream {
-    pub fn read(&self, buffer: Vec<u8>, timeout: Option<u64>) -> Result<usize> {
-        let timeout = timeout.map(|time| Duration::from_millis(time));
-        let safe_buffer = Arc::new(Mutex::new(buffer));
+    pub fn read(&self, amount: usize, timeout: Timeout, cancel: Option<Cancel>) -> Result<Vec<u8>> {
+        let safe_buffer = Arc::new(Mutex::new(vec![0; amount]));
         let (sender, receiver) = channel();
         let response_channel = Mutex::new(sender);
-        let event = StreamEvent::Read(response_channel, safe_buffer.clone(), timeout);
+        let event = StreamEvent::Read(response_channel, safe_buffer.clone(), timeout, cancel);
+        self.send(event)?;
+        let length = receiver.recv()
+            .context("Error receiving data from runtime.")??;
+        let mut buffer = safe_buffer
+            .try_lock()
+            .map_err(|_err| anyhow::anyhow!("Read buffer still in use."))?;
+        buffer.truncate(length);
+        Ok(std::mem::take(&mut *buffer))
+    }
+
+    pub fn write(&self, buffer: Vec<u8>, timeout: Timeout) -> Result<()> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = StreamEvent::Write(response_channel, buffer, timeout);
         self.send(event)?;
         receiver.recv()
             .context("Error receiving data from runtime.")?
     }
 
-    pub fn write(&self, buffer: Vec<u8>) -> Result<()> {
+    pub fn set_priority(&self, priority: i32) -> Result<()> {
         let (sender, receiver) = channel();
         let response_channel = Mutex::new(sender);
-        let event = StreamEvent::Write(response_channel, buffer);
+        let event = StreamEvent::SetPriority(response_channel, priority);
         self.send(event)?;
         receiver.recv()
             .context("Error receiving data from runtime.")?
     }
 
-    pub fn close_stream(&self, error_code: ApplicationError) -> Result<()> {
-        let event = StreamEvent::CloseStream(error_code);
+    pub fn info(&self) -> Result<StreamInfo> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = StreamEvent::Info(response_channel);
         self.send(event)?;
-        Ok(())
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
     }
-}
 
-impl From<AsyncSender<SocketEvent>> for Socket {
-    fn from(sender : AsyncSender<SocketEvent>) -> Self {
-        Self(sender)
+    pub fn split(&self) -> Result<(Stream, Stream)> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = StreamEvent::Split(response_channel);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
     }
-}
 
-impl From<AsyncSender<NewSocketEvent>> for NewSocket {
-    fn from(sender : AsyncSender<NewSocketEvent>) -> Self {
-        Self(sender)
+    pub fn close_stream(&self, error_code: ApplicationError) -> Result<()> {
+        let event = StreamEvent::CloseStream(error_code);
+        self.send(event)?;
+        Ok(())
     }
-}
 
-impl From<AsyncSender<StreamEvent>> for Stream {
-    fn from(sender : AsyncSender<StreamEvent>) -> Self {
-        Self(sender)
+    pub fn controlling_process(&self, pid: LocalPid) -> Result<()> {
+        let (sender, receiver) = channel();
+        let response_channel = Mutex::new(sender);
+        let event = StreamEvent::ControllingProcess(response_channel, pid);
+        self.send(event)?;
+        receiver.recv()
+            .context("Error receiving data from runtime.")?
     }
 }
+
@@ -1,9 +1,21 @@
 /// This combines the various errors of quinn into a single Error enum.
+use err_derive::{ Error };
+
 use quinn::{
     VarInt,
 };
 
-#[derive(Debug, NifUntaggedEnum)]
+/// Returned when a socket or stream has too many operations waiting on the runtime.
+#[derive(Debug, Error)]
+#[error(display = "busy")]
+pub struct Busy;
+
+/// Returned when a call is cancelled before it finishes.
+#[derive(Debug, Error)]
+#[error(display = "cancelled")]
+pub struct Cancelled;
+
+#[derive(Clone, Copy, Debug, NifUntaggedEnum)]
 pub enum ApplicationError {
     Error(u32),
     LargeError(u64),
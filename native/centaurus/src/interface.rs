@@ -4,6 +4,8 @@ pub mod api;
 pub mod certs;
 pub mod config_impl;
 pub mod convert;
+pub mod logger;
+pub mod monitor;
 pub mod types;
 mod test;
 use types::{
@@ -11,6 +13,7 @@ use types::{
     BeamStream,
     NewSocket,
     NewSocketInterior,
+    OperationInterior,
     Socket,
     SocketInterior,
     Stream,
@@ -29,6 +32,29 @@ atoms! {
     uni,
     peer,
     host,
+    quic_stream,
+    quic_ready,
+    quic_peer_address,
+    quic_connected,
+    quic_closed,
+    quic_handshake_failed,
+    quic_idle_timeout,
+    quic_goaway,
+    busy,
+    local,
+    infinity,
+    cancelled,
+    centaurus_log,
+    centaurus_telemetry,
+    centaurus,
+    connection,
+    stream,
+    start,
+    stop,
+    open,
+    close,
+    duration,
+    handshake_duration,
 }
 
 init!(
@@ -50,13 +76,33 @@ init!(
         test::test_quic_opts,
         test::test_quic_socket,
         test::test_quic_stream,
+        test::live_tasks,
         api::accept,
+        api::admission_stats,
         api::connect,
+        api::connect_from,
         api::close,
+        api::close_endpoint,
+        api::accept_stream,
+        api::cancel,
         api::close_stream,
+        api::controlling_process,
+        api::id,
         api::listen,
+        api::local_address,
+        api::metrics,
         api::open_stream,
+        api::operation,
+        api::peer,
+        api::rebind,
+        api::set_keep_alive,
+        api::set_log_handler,
+        api::set_priority,
+        api::shutdown,
+        api::split,
         api::start,
+        api::stream_info,
+        api::wait_idle,
         api::read,
         api::write,
     ],
@@ -81,6 +127,9 @@ fn setup_runtime(env: Env, _: Term) -> bool {
     resource!(Socket, env);
     // An open stream connection
     resource!(Stream, env);
-    true
+    // A call that can be cancelled
+    resource!(OperationInterior, env);
+    // Monitors on the owners of sockets and streams
+    monitor::open_resource_type(env)
 }
 
@@ -1,14 +1,26 @@
 //! The Elixir entrypoint.
+use super::logger;
 use super::types::{
+    AdmissionStats,
     BeamSocket,
     BeamStream,
+    Bound,
     Error,
+    Identified,
+    LogLevel,
+    Metrics,
     NewSocket,
+    Operation,
+    Owned,
+    PeerAddr,
     Socket,
     SocketType,
     SocketAddr,
+    Status,
     Stream,
+    StreamInfo,
     StreamType,
+    Timeout,
 };
 
 use crate::conn;
@@ -16,6 +28,8 @@ use crate::error::{ ApplicationError };
 
 use anyhow::{ Context };
 
+use rustler::{ Binary, Env, LocalPid, OwnedBinary };
+
 use std::convert::TryInto;
 
 type Result<T> = std::result::Result<T, Error>;
@@ -28,41 +42,126 @@ fn start() {
 }
 
 /// listen(socket_config, stream_config)
+/// The listener is owned by the socket_pid, or the calling process when there is none.
 #[rustler::nif]
-fn listen(socket_config: BeamSocket, stream_config: BeamStream) -> Result<NewSocket> {
+fn listen(env: Env, socket_config: BeamSocket, stream_config: BeamStream) -> Result<NewSocket> {
+    let socket_config = socket_config.or_owner(env.pid());
     let socket = conn::NewSocket::new(SocketType::Server, socket_config.into(), stream_config.into())?;
     Ok(socket.into())
 }
 
 /// connect(socket_config, stream_config, address, timeout)
+/// The connection is owned by the socket_pid, or the calling process when there is none.
 #[rustler::nif]
-fn connect(socket_config: BeamSocket, stream_config: BeamStream, address: SocketAddr, timeout: Option<u64>) -> Result<Socket> {
+fn connect(env: Env, socket_config: BeamSocket, stream_config: BeamStream, address: PeerAddr, timeout: Timeout) -> Result<Socket> {
+    let socket_config = socket_config.or_owner(env.pid());
     let socket = conn::NewSocket::new(SocketType::Client, socket_config.into(), stream_config.into())?
-        .connect(*address, timeout)?;
+        .connect(address, timeout)?;
 //        .context("Connect Failure.")?;
     Ok(socket.into())
 }
 
-/// accept(socket, timeout)
+/// peer(socket_config, stream_config)
+/// Opens an endpoint that both accepts connections and connects to other peers. It is owned as
+/// a listener is.
+#[rustler::nif]
+fn peer(env: Env, socket_config: BeamSocket, stream_config: BeamStream) -> Result<NewSocket> {
+    let socket_config = socket_config.or_owner(env.pid());
+    let socket = conn::NewSocket::new(SocketType::Peer, socket_config.into(), stream_config.into())?;
+    Ok(socket.into())
+}
+
+/// connect_from(socket, address, timeout)
+/// Connects from an endpoint opened with peer.
+#[rustler::nif]
+fn connect_from(quic_socket: NewSocket, address: PeerAddr, timeout: Timeout) -> Result<Socket> {
+    let socket = quic_socket.connect(address, timeout)?;
+    Ok(socket.into())
+}
+
+/// accept(socket, timeout, operation, owner)
+/// The accepted connection is owned by owner, the process that called accept.
 #[rustler::nif]
-fn accept(quic_socket: NewSocket, timeout: Option<u64>) -> Result<Socket> {
-    let socket = quic_socket.accept(timeout)?
+fn accept(quic_socket: NewSocket, timeout: Timeout, operation: Option<Operation>, owner: LocalPid) -> Result<Socket> {
+    let cancel = operation.map(|operation| operation.start()).transpose()?;
+    let socket = quic_socket.accept(owner, timeout, cancel)?
 //        .context("Accept Failure.")?
         .into();
     Ok(socket)
 }
 
+/// operation()
+/// A reference to pass to accept or read so another process can cancel the call.
+#[rustler::nif]
+fn operation() -> Result<Operation> {
+    Ok(conn::Operation::new().into())
+}
+
+/// cancel(operation)
+/// The cancelled call returns {:error, :cancelled}.
+#[rustler::nif]
+fn cancel(operation: Operation) -> Status {
+    operation.cancel()
+        .context("Could not cancel operation.")
+        .into()
+}
+
+/// id(handle)
+/// The id the runtime's messages use for the socket or stream.
+#[rustler::nif]
+fn id(handle: Identified) -> u64 {
+    match handle {
+        Identified::NewSocket(quic_socket) => quic_socket.id(),
+        Identified::Socket(quic_socket) => quic_socket.id(),
+        Identified::Stream(quic_stream) => quic_stream.id(),
+    }
+}
+
+/// admission_stats(socket)
+#[rustler::nif]
+fn admission_stats(quic_socket: NewSocket) -> Result<AdmissionStats> {
+    Ok(quic_socket.admission_stats())
+}
+
+/// metrics()
+/// Counters across every socket since the runtime started.
+#[rustler::nif]
+fn metrics() -> Result<Metrics> {
+    Ok(crate::metrics::snapshot())
+}
+
+/// set_log_handler(pid, level)
+/// Sends the runtime's log events at the level or above to pid as {:centaurus_log, level, message}.
+/// A nil pid stops sending them.
+#[rustler::nif]
+fn set_log_handler(pid: Option<LocalPid>, level: LogLevel) -> Status {
+    logger::set_handler(pid, level)
+        .context("Could not set log handler.")
+        .into()
+}
+
+/// accept_stream(socket, stream_type, timeout)
+/// Takes the next stream of the given type opened by the peer. The caller owns the stream.
+#[rustler::nif]
+fn accept_stream(env: Env, quic_socket: Socket, stream_type: StreamType, timeout: Timeout) -> Result<Stream> {
+    let stream = quic_socket.accept_stream(env.pid(), stream_type, timeout)
+        .context("Error accepting stream.")?
+        .into();
+    Ok(stream)
+}
+
 /// open_stream(socket, stream_type)
+/// The stream is owned by the stream config's stream_pid, or the caller when there is none.
 #[rustler::nif]
-fn open_stream(quic_socket: Socket, stream_type: StreamType) -> Result<Stream> {
+fn open_stream(env: Env, quic_socket: Socket, stream_type: StreamType) -> Result<Stream> {
     let stream : Stream = match stream_type {
         StreamType::Uni => {
-            quic_socket.new_uni_stream()
+            quic_socket.new_uni_stream(env.pid())
                 .context("Error opening new unidirectional stream.")?
                 .into()
         },
         StreamType::Bi => {
-            quic_socket.new_bi_stream()
+            quic_socket.new_bi_stream(env.pid())
                 .context("Error opening new bidirectional stream.")?
                 .into()
         },
@@ -70,38 +169,142 @@ fn open_stream(quic_socket: Socket, stream_type: StreamType) -> Result<Stream> {
     Ok(stream)
 }
 
-/// read(stream, amount, timeout)
+/// set_keep_alive(socket, interval)
+/// Pings the peer every interval milliseconds while the connection is open, 0 to stop.
+#[rustler::nif]
+fn set_keep_alive(quic_socket: Socket, interval: u64) -> Status {
+    quic_socket.set_keep_alive(interval)
+        .context("Could not set keep-alive.")
+        .into()
+}
+
+/// set_priority(stream, priority)
+/// Streams with a higher priority are sent before streams with a lower one.
+#[rustler::nif]
+fn set_priority(quic_stream: Stream, priority: i32) -> Status {
+    quic_stream.set_priority(priority)
+        .context("Could not set stream priority.")
+        .into()
+}
+
+/// stream_info(stream)
+#[rustler::nif]
+fn stream_info(quic_stream: Stream) -> Result<StreamInfo> {
+    let info = quic_stream.info()
+        .context("Could not get stream info.")?;
+    Ok(info)
+}
+
+/// split(stream)
+/// Returns the sending and receiving halves of a bi stream as separate streams.
+#[rustler::nif]
+fn split(quic_stream: Stream) -> Result<(Stream, Stream)> {
+    let (send, recv) = quic_stream.split()
+        .context("Could not split stream.")?;
+    Ok((send.into(), recv.into()))
+}
+
+/// read(stream, amount, timeout, operation)
+/// Returns up to amount bytes, as soon as any data is available.
 #[rustler::nif]
-fn read(quic_stream: Stream, amount: u64, timeout: Option<u64>) -> Result<(usize, Vec<u8>)> {
+fn read<'a>(env: Env<'a>, quic_stream: Stream, amount: u64, timeout: Timeout, operation: Option<Operation>) -> Result<Binary<'a>> {
     let amount = amount.try_into().context("Invalid Read amount.")?;
-    let buffer = Vec::with_capacity(amount);
-    match quic_stream.read(buffer.clone(), timeout) {
-        Ok(length) => Ok((length, buffer)),
-        Err(error) => Err(error).context("Read Error.").map_err(|err| err.into()),
-    }
+    let cancel = operation.map(|operation| operation.start()).transpose()?;
+    let data = quic_stream.read(amount, timeout, cancel).context("Read Error.")?;
+    let mut binary = OwnedBinary::new(data.len())
+        .ok_or_else(|| anyhow::anyhow!("Could not allocate binary."))?;
+    binary.as_mut_slice().copy_from_slice(&data);
+    Ok(binary.release(env))
 }
 
-/// write(stream, data)
+/// write(stream, data, timeout)
 #[rustler::nif]
-fn write<'a>(quic_stream: Stream, data: &'a str) -> Result<()> {
+fn write<'a>(quic_stream: Stream, data: &'a str, timeout: Timeout) -> Status {
     let buffer = Vec::from(data);
-    quic_stream.write(buffer).context("Write Error.")?;
-    Ok(())
+    quic_stream.write(buffer, timeout)
+        .context("Write Error.")
+        .into()
 }
 
 /// close(socket, error_code, reason)
+/// Closing a listener closes every connection it accepted.
+#[rustler::nif]
+fn close<'a>(handle: Bound, error_code: ApplicationError, reason: Option<&'a str>) -> Status {
+    let reason = reason.map(|s| s.to_owned());
+    match handle {
+        Bound::NewSocket(quic_socket) => quic_socket.close(error_code, reason),
+        Bound::Socket(quic_socket) => quic_socket.close(error_code, reason),
+    }.context("Could not close socket.")
+        .into()
+}
+
+/// close_endpoint(socket, error_code, reason)
+/// Closes every connection on the endpoint. The socket stays open for wait_idle.
+#[rustler::nif]
+fn close_endpoint<'a>(quic_socket: NewSocket, error_code: ApplicationError, reason: Option<&'a str>) -> Status {
+    quic_socket.close_endpoint(error_code, reason.map(|s| s.to_owned()))
+        .context("Could not close endpoint.")
+        .into()
+}
+
+/// wait_idle(socket, timeout)
+/// Waits for the endpoint's connections to finish closing.
 #[rustler::nif]
-fn close<'a>(quic_socket: Socket, error_code: ApplicationError, reason: Option<&'a str>) -> Result<()> {
-    quic_socket.close(error_code, reason.map(|s| s.to_owned()))
-        .context("Could not close socket.")?;
-    Ok(())
+fn wait_idle(quic_socket: NewSocket, timeout: Timeout) -> Status {
+    quic_socket.wait_idle(timeout)
+        .context("Could not wait for the endpoint.")
+        .into()
+}
+
+/// shutdown(socket, timeout, close)
+/// Stops accepting and waits for the accepted connections to end, closing them first when
+/// close is given. Returns how many ended before the timeout.
+#[rustler::nif]
+fn shutdown<'a>(quic_socket: NewSocket, timeout: Timeout, close: Option<(ApplicationError, &'a str)>) -> Result<usize> {
+    let close = close.map(|(error_code, reason)| (error_code, Some(reason.to_owned())));
+    let drained = quic_socket.shutdown(timeout, close)
+        .context("Could not shut down socket.")?;
+    Ok(drained)
 }
 
 /// close_stream(stream, error_code)
 #[rustler::nif]
-fn close_stream(quic_stream: Stream, error_code: ApplicationError) -> Result<()> {
+fn close_stream(quic_stream: Stream, error_code: ApplicationError) -> Status {
     quic_stream.close_stream(error_code)
-        .context("Could not close stream.")?;
-    Ok(())
+        .context("Could not close stream.")
+        .into()
+}
+
+
+/// local_address(socket)
+/// The address the socket is bound to. Reports the port the system picked when binding to port 0.
+#[rustler::nif]
+fn local_address(handle: Bound) -> Result<SocketAddr> {
+    let address = match handle {
+        Bound::NewSocket(quic_socket) => quic_socket.local_address(),
+        Bound::Socket(quic_socket) => quic_socket.local_address(),
+    }.context("Could not read local address.")?;
+    Ok(SocketAddr(address))
+}
+
+/// rebind(socket, address)
+/// Moves the socket's endpoint to a new local address. Open connections migrate with it.
+#[rustler::nif]
+fn rebind(handle: Bound, address: SocketAddr) -> Status {
+    match handle {
+        Bound::NewSocket(quic_socket) => quic_socket.rebind(*address),
+        Bound::Socket(quic_socket) => quic_socket.rebind(*address),
+    }.context("Could not rebind socket.")
+        .into()
 }
 
+/// controlling_process(socket | stream, pid)
+#[rustler::nif]
+fn controlling_process(handle: Owned, pid: LocalPid) -> Status {
+    match handle {
+        Owned::NewSocket(quic_socket) => quic_socket.controlling_process(pid),
+        Owned::Socket(quic_socket) => quic_socket.controlling_process(pid),
+        Owned::Stream(quic_stream) => quic_stream.controlling_process(pid),
+    }.context("Could not change the controlling process.")
+        .into()
+}
@@ -1,8 +1,10 @@
-//! Contains the impls related to Certificates and Private Keys.
+//! Contains the impls related to Certificates, Private Keys and the Token and Reset Keys.
 
 use super::types::{
     Certificates,
     PrivateKey,
+    ResetKey,
+    TokenKey,
 };
 
 use anyhow::{ Context, Result };
@@ -55,3 +57,19 @@ impl PrivateKey {
     }
 }
 
+
+impl TokenKey {
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let TokenKey(path) = self;
+        fs::read(path).ok()
+            .context("Token Key File not found.")
+    }
+}
+
+impl ResetKey {
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let ResetKey(path) = self;
+        fs::read(path).ok()
+            .context("Reset Key File not found.")
+    }
+}
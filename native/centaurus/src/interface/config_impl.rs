@@ -1,10 +1,16 @@
 //! Contains the config impls for Quic. See centaurus::config for details.
 
 use super::types::{
+    AcceptMode,
     BeamSocket,
+    BeamStream,
     QuicSocket,
+    QuicStream,
     SocketAddr,
     SocketRef,
+    StreamAcceptor,
+    StreamRef,
+    StreamType,
 };
 
 use anyhow::{ Context, Result };
@@ -15,14 +21,36 @@ use quinn::{
 };
 
 use rustler::{
+    LocalPid,
     OwnedEnv,
 };
 
+use socket2::{ Domain, Protocol, Socket, Type };
+
+use std::{
+    net::{ UdpSocket },
+    sync::{ Arc },
+};
+
+#[cfg(unix)]
+use std::os::unix::io::{ FromRawFd, IntoRawFd, RawFd };
+
 impl SocketRef {
     pub fn address(&self) -> Result<std::net::SocketAddr> {
         self.0.address()
     }
 
+    /// The UDP socket for the endpoint. Uses the config's fd if there is one, otherwise
+    /// binds the bind address.
+    pub fn udp_socket(&self) -> Result<UdpSocket> {
+        self.0.udp_socket()
+    }
+
+    /// Binds a new UDP socket with the config's socket options.
+    pub fn bind(&self, address: std::net::SocketAddr) -> Result<UdpSocket> {
+        self.0.bind(address)
+    }
+
     pub fn certs(&self) -> Result<Certificate> {
         self.0.certs()
     }
@@ -38,13 +66,56 @@ impl SocketRef {
     pub fn send<T : rustler::Encoder>(&self, msg: T) -> Result<()> {
         self.0.send(msg)
     }
+
+    /// Sends the message to the config's telemetry_pid, if it has one.
+    pub fn send_telemetry<T : rustler::Encoder>(&self, msg: T) -> Result<()> {
+        self.0.send_telemetry(msg)
+    }
     
     pub fn server_name(&self) -> Result<String> {
         self.0.server_name()
     }
+
+    pub fn owner(&self) -> Option<LocalPid> {
+        self.0.owner()
+    }
+
+    /// Picks the process that receives the count'th stream of the given type opened by the peer.
+    /// None means the stream waits for accept_stream.
+    pub fn stream_owner(&self, stream_type: StreamType, count: usize) -> Option<LocalPid> {
+        self.0.stream_owner(stream_type, count)
+    }
+
+    /// Returns a copy of the config owned by the given process.
+    pub fn with_owner(&self, pid: LocalPid) -> SocketRef {
+        let mut config = (*self.0).clone();
+        config.socket_pid = Some(QuicSocket::Pid(pid));
+        SocketRef(Arc::new(config))
+    }
+}
+
+impl StreamRef {
+    pub fn owner(&self) -> Option<LocalPid> {
+        self.0.owner()
+    }
+
+    /// Returns a copy of the config owned by the given process.
+    pub fn with_owner(&self, pid: LocalPid) -> StreamRef {
+        let mut config = (*self.0).clone();
+        config.stream_pid = Some(QuicStream::Pid(pid));
+        StreamRef(Arc::new(config))
+    }
 }
 
 impl BeamSocket {
+    /// The config with the given process as its owner, unless it names a socket_pid already.
+    pub fn or_owner(mut self, pid: LocalPid) -> BeamSocket {
+        if self.socket_pid.is_none() {
+            self.socket_pid = Some(QuicSocket::Pid(pid));
+        }
+        self
+    }
+
     fn address(&self) -> Result<std::net::SocketAddr> {
         self.bind_address
             .map(|SocketAddr(socket)| socket)
@@ -52,10 +123,64 @@ impl BeamSocket {
             .context("Local Socket Address is required.")
     }
 
+    fn udp_socket(&self) -> Result<UdpSocket> {
+        match self.fd {
+            Some(fd) => self.adopt(fd),
+            None => self.bind(self.address()?),
+        }
+    }
+
+    #[cfg(unix)]
+    fn adopt(&self, fd: RawFd) -> Result<UdpSocket> {
+        if socket_type(fd)? != libc::SOCK_DGRAM {
+            return Err(anyhow::anyhow!("Socket fd is not a datagram socket."))
+        }
+        // Work on a duplicate so whoever opened the fd keeps theirs.
+        let opened = unsafe { UdpSocket::from_raw_fd(fd) };
+        let duplicate = opened.try_clone();
+        opened.into_raw_fd();
+        let socket = Socket::from(duplicate.context("Error duplicating socket fd.")?);
+        self.set_buffers(&socket)?;
+        Ok(socket.into_udp_socket())
+    }
+
+    #[cfg(not(unix))]
+    fn adopt(&self, _fd: i32) -> Result<UdpSocket> {
+        Err(anyhow::anyhow!("Opening a socket from an fd is only supported on Unix."))
+    }
+
+    fn bind(&self, address: std::net::SocketAddr) -> Result<UdpSocket> {
+        let domain = if address.is_ipv6() { Domain::ipv6() } else { Domain::ipv4() };
+        let socket = Socket::new(domain, Type::dgram(), Some(Protocol::udp()))
+            .context("Error opening socket.")?;
+        if let Some(reuse_port) = self.options.reuse_port {
+            socket.set_reuse_port(reuse_port).context("Error setting SO_REUSEPORT.")?;
+        }
+        if let (true, Some(ipv6_only)) = (address.is_ipv6(), self.options.ipv6_only) {
+            socket.set_only_v6(ipv6_only).context("Error setting IPV6_V6ONLY.")?;
+        }
+        self.set_buffers(&socket)?;
+        socket.bind(&address.into()).context("Error binding socket.")?;
+        Ok(socket.into_udp_socket())
+    }
+
+    fn set_buffers(&self, socket: &Socket) -> Result<()> {
+        if let Some(size) = self.options.recv_buffer {
+            socket.set_recv_buffer_size(size).context("Error setting receive buffer size.")?;
+        }
+        if let Some(size) = self.options.send_buffer {
+            socket.set_send_buffer_size(size).context("Error setting send buffer size.")?;
+        }
+        Ok(())
+    }
+
+    // The certificate authority trusted when connecting. Falls back to the socket's own
+    // certificates.
     fn certs(&self) -> Result<Certificate> {
-        self.certificates
+        self.trusted_certificates
             .as_ref()
-            .unwrap()
+            .or_else(|| self.certificates.as_ref())
+            .context("Certificates are required.")?
             .as_cert()
             .context("Error reading Certificate")
     }
@@ -77,18 +202,80 @@ impl BeamSocket {
     }
 
     fn send<T : rustler::Encoder>(&self, msg: T) -> Result<()> {
-        let mut env = OwnedEnv::new();
         match &self.socket_pid {
-            Some(QuicSocket::Pid(pid)) => {
-                env.send_and_clear(pid, |env| msg.encode(env));
-                Ok(())
-            },
+            Some(QuicSocket::Pid(pid)) => send_to(pid, msg),
             _ => Err(anyhow::anyhow!("Invalid Pid for sending data."))
         }
     }
+
+    fn send_telemetry<T : rustler::Encoder>(&self, msg: T) -> Result<()> {
+        match &self.telemetry_pid {
+            Some(QuicSocket::Pid(pid)) => send_to(pid, msg),
+            None => Ok(()),
+        }
+    }
     
     fn server_name(&self) -> Result<String> {
         Ok(self.server_name.clone())
     }
+
+    fn owner(&self) -> Option<LocalPid> {
+        match &self.socket_pid {
+            Some(QuicSocket::Pid(pid)) => Some(pid.clone()),
+            None => None,
+        }
+    }
+
+    fn stream_owner(&self, stream_type: StreamType, count: usize) -> Option<LocalPid> {
+        match &self.stream_acceptor {
+            None => self.owner(),
+            Some(StreamAcceptor { mode: AcceptMode::Passive, .. }) => None,
+            Some(StreamAcceptor { mode: AcceptMode::Active, bi, uni }) => {
+                let pids = match stream_type {
+                    StreamType::Bi => bi,
+                    StreamType::Uni => uni,
+                };
+                match pids.get(count % pids.len().max(1)) {
+                    Some(QuicStream::Pid(pid)) => Some(pid.clone()),
+                    None => self.owner(),
+                }
+            },
+        }
+    }
+}
+
+// The SO_TYPE of the socket, such as SOCK_DGRAM. Read before the fd is handed to quinn, which
+// only works with UDP sockets.
+#[cfg(unix)]
+fn socket_type(fd: RawFd) -> Result<libc::c_int> {
+    let mut socket_type : libc::c_int = 0;
+    let mut length = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(fd,
+                         libc::SOL_SOCKET,
+                         libc::SO_TYPE,
+                         &mut socket_type as *mut libc::c_int as *mut libc::c_void,
+                         &mut length)
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error()).context("Invalid socket fd.")
+    }
+    Ok(socket_type)
+}
+
+/// Sends the message to the process from outside of a Nif call.
+pub fn send_to<T : rustler::Encoder>(pid: &LocalPid, msg: T) -> Result<()> {
+    let mut env = OwnedEnv::new();
+    env.send_and_clear(pid, |env| msg.encode(env));
+    Ok(())
+}
+
+impl BeamStream {
+    fn owner(&self) -> Option<LocalPid> {
+        match &self.stream_pid {
+            Some(QuicStream::Pid(pid)) => Some(pid.clone()),
+            None => None,
+        }
+    }
 }
 
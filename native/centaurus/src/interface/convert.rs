@@ -3,34 +3,144 @@
 use super::types::{
     Certificates,
     Error,
+    PeerAddr,
     PrivateKey,
+    ResetKey,
     SocketAddr,
+    Status,
+    Timeout,
+    TokenKey,
 };
 
-use rustler::{ Decoder, Encoder, Env, Term };
+use crate::error::{ Busy, Cancelled };
 
-use std::path::PathBuf;
+use rustler::{ Atom, Decoder, Encoder, Env, Term };
+use rustler::types::tuple::{ get_tuple, make_tuple };
+
+use std::{
+    net::{ IpAddr, Ipv4Addr, Ipv6Addr },
+    path::{ PathBuf },
+};
 
 impl<'a> Encoder for Error {
     fn encode<'b>(&self, env: Env<'b>) -> Term<'b> {
-        self.to_string().encode(env)
+        if self.chain().any(|cause| cause.is::<Busy>()) {
+            super::busy().encode(env)
+        } else if self.chain().any(|cause| cause.is::<Cancelled>()) {
+            super::cancelled().encode(env)
+        } else {
+            self.to_string().encode(env)
+        }
+    }
+}
+
+impl<'a> Encoder for Status {
+    fn encode<'b>(&self, env: Env<'b>) -> Term<'b> {
+        match &self.0 {
+            Ok(()) => super::ok().encode(env),
+            Err(error) => (super::error(), error).encode(env),
+        }
     }
 }
 
 impl<'a> Decoder<'a> for SocketAddr {
     fn decode(term : Term<'a>) -> Result<SocketAddr, rustler::Error> {
-        let raw : &str = Decoder::decode(term)?;
-        let sock_addr : std::net::SocketAddr = raw
-            .parse()
+        if let Ok(raw) = term.decode::<&str>() {
+            let sock_addr : std::net::SocketAddr = raw
+                .parse()
+                .or(Err(rustler::Error::Term(Box::new("Invalid Socket Address"))))?;
+            return Ok(SocketAddr(sock_addr))
+        }
+        let parts = get_tuple(term)
             .or(Err(rustler::Error::Term(Box::new("Invalid Socket Address"))))?;
-        Ok(SocketAddr(sock_addr))
+        match parts.as_slice() {
+            [family, _path] if family.decode::<Atom>().map_or(false, |atom| atom == super::local()) => {
+                Err(rustler::Error::Term(Box::new("Local Socket Addresses are not supported")))
+            },
+            [ip, port] => {
+                let port : u16 = port.decode()
+                    .or(Err(rustler::Error::Term(Box::new("Invalid Port"))))?;
+                Ok(SocketAddr(std::net::SocketAddr::new(decode_ip(*ip)?, port)))
+            },
+            _ => Err(rustler::Error::Term(Box::new("Invalid Socket Address"))),
+        }
+    }
+}
+
+// {127, 0, 0, 1} or {0, 0, 0, 0, 0, 0, 0, 1}, as used by :inet.
+fn decode_ip<'a>(term : Term<'a>) -> Result<IpAddr, rustler::Error> {
+    let parts = get_tuple(term)
+        .or(Err(rustler::Error::Term(Box::new("Invalid IP Address"))))?;
+    match parts.len() {
+        4 => {
+            let octets = parts.iter()
+                .map(|part| part.decode::<u8>())
+                .collect::<Result<Vec<u8>, rustler::Error>>()
+                .or(Err(rustler::Error::Term(Box::new("Invalid IPv4 Address"))))?;
+            Ok(IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])))
+        },
+        8 => {
+            let segments = parts.iter()
+                .map(|part| part.decode::<u16>())
+                .collect::<Result<Vec<u16>, rustler::Error>>()
+                .or(Err(rustler::Error::Term(Box::new("Invalid IPv6 Address"))))?;
+            Ok(IpAddr::V6(Ipv6Addr::new(segments[0], segments[1], segments[2], segments[3],
+                                        segments[4], segments[5], segments[6], segments[7])))
+        },
+        _ => Err(rustler::Error::Term(Box::new("Invalid IP Address"))),
+    }
+}
+
+fn encode_ip<'b>(ip : &IpAddr, env : Env<'b>) -> Term<'b> {
+    let parts : Vec<Term<'b>> = match ip {
+        IpAddr::V4(ip) => ip.octets().iter().map(|octet| octet.encode(env)).collect(),
+        IpAddr::V6(ip) => ip.segments().iter().map(|segment| segment.encode(env)).collect(),
+    };
+    make_tuple(env, &parts)
+}
+
+impl<'a> Decoder<'a> for PeerAddr {
+    fn decode(term : Term<'a>) -> Result<PeerAddr, rustler::Error> {
+        if let Ok(SocketAddr(sock_addr)) = term.decode() {
+            return Ok(PeerAddr::Addr(sock_addr))
+        }
+        let (host, port) : (String, u16) = match term.decode::<&str>() {
+            Ok(raw) => {
+                let mut parts = raw.rsplitn(2, ':');
+                let port = parts.next()
+                    .and_then(|port| port.parse().ok())
+                    .ok_or(rustler::Error::Term(Box::new("Invalid Port")))?;
+                let host = parts.next()
+                    .ok_or(rustler::Error::Term(Box::new("Invalid Peer Address")))?;
+                (host.to_string(), port)
+            },
+            Err(_) => term.decode()
+                .or(Err(rustler::Error::Term(Box::new("Invalid Peer Address"))))?,
+        };
+        match host.parse::<IpAddr>() {
+            Ok(ip) => Ok(PeerAddr::Addr(std::net::SocketAddr::new(ip, port))),
+            Err(_) => Ok(PeerAddr::Host(host, port)),
+        }
     }
 }
 
 impl<'a> Encoder for SocketAddr {
     fn encode<'b>(&self, env: Env<'b>) -> Term<'b> {
         let SocketAddr(sock_addr) = self;
-        sock_addr.to_string().encode(env)
+        (encode_ip(&sock_addr.ip(), env), sock_addr.port()).encode(env)
+    }
+}
+
+impl<'a> Decoder<'a> for Timeout {
+    fn decode(term : Term<'a>) -> Result<Timeout, rustler::Error> {
+        if let Ok(millis) = term.decode::<u64>() {
+            return Ok(Timeout::Millis(millis))
+        }
+        match term.decode::<Atom>() {
+            Ok(atom) if atom == super::infinity() => Ok(Timeout::Infinity),
+            Ok(atom) if atom == rustler::types::atom::nil() => Ok(Timeout::Default),
+            _ => Err(rustler::Error::Term(Box::new("Invalid Timeout"))),
+        }
     }
 }
 
@@ -68,3 +178,33 @@ impl<'a> Encoder for PrivateKey {
     }
 }
 
+impl<'a> Decoder<'a> for TokenKey {
+    fn decode(term : Term<'a>) -> Result<Self, rustler::Error> {
+        let raw : &str = Decoder::decode(term)
+            .or(Err(rustler::Error::Term(Box::new("Invalid Token Key Path"))))?;
+        Ok(TokenKey(PathBuf::from(raw)))
+    }
+}
+
+impl<'a> Encoder for TokenKey {
+    fn encode<'b>(&self, env: Env<'b>) -> Term<'b> {
+        let TokenKey(path) = self;
+        path.to_str().encode(env)
+    }
+}
+
+impl<'a> Decoder<'a> for ResetKey {
+    fn decode(term : Term<'a>) -> Result<Self, rustler::Error> {
+        let raw : &str = Decoder::decode(term)
+            .or(Err(rustler::Error::Term(Box::new("Invalid Reset Key Path"))))?;
+        Ok(ResetKey(PathBuf::from(raw)))
+    }
+}
+
+impl<'a> Encoder for ResetKey {
+    fn encode<'b>(&self, env: Env<'b>) -> Term<'b> {
+        let ResetKey(path) = self;
+        path.to_str().encode(env)
+    }
+}
+
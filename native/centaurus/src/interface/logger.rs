@@ -0,0 +1,153 @@
+//! Forwards the runtime's tracing events to a process on the Elixir side, which hands them to
+//! Logger. Nothing is forwarded until a process registers with set_log_handler.
+
+use super::config_impl::{ send_to };
+use super::types::{ LogLevel };
+use crate::runtime::{ POOL_THREAD_NAME };
+
+use anyhow::{ Result };
+
+use once_cell::sync::{ Lazy };
+
+use rustler::{ LocalPid };
+
+use tracing::{
+    Event,
+    Level,
+    Subscriber,
+    field::{ Field, Visit },
+    span::{ Attributes, Id },
+};
+use tracing_subscriber::{
+    layer::{ self, Layer, SubscriberExt },
+    registry::{ LookupSpan, Registry },
+};
+
+use std::{
+    fmt::{ self, Write },
+    sync::{ RwLock },
+};
+
+// The process log events go to and the most verbose level it wants.
+struct Handler {
+    pid: LocalPid,
+    level: Level,
+}
+
+// The fields a span was created with, formatted once so every event inside it can reuse them.
+struct SpanFields(String);
+
+// Sends each event to the handler as {:centaurus_log, level, message}.
+struct BeamLayer;
+
+// Collects an event's message and its other fields as "key=value" pairs.
+#[derive(Default)]
+struct Message {
+    message: String,
+    fields: String,
+}
+
+// The subscriber is installed the first time the handler is used.
+static HANDLER : Lazy<RwLock<Option<Handler>>> = Lazy::new(|| {
+    // Another subscriber may already be the global default when the NIF is embedded.
+    tracing::subscriber::set_global_default(Registry::default().with(BeamLayer)).ok();
+    RwLock::new(None)
+});
+
+/// Forwards events at the level or less verbose to the pid. A pid of None stops forwarding.
+pub fn set_handler(pid: Option<LocalPid>, level: LogLevel) -> Result<()> {
+    let mut handler = HANDLER
+        .write()
+        .map_err(|_err| anyhow::anyhow!("Log Handler Poisoned."))?;
+    *handler = pid.map(|pid| Handler { pid, level: level.into() });
+    Ok(())
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Error => Level::ERROR,
+        }
+    }
+}
+
+impl From<&Level> for LogLevel {
+    fn from(level: &Level) -> Self {
+        if *level == Level::ERROR {
+            LogLevel::Error
+        } else if *level == Level::WARN {
+            LogLevel::Warn
+        } else if *level == Level::INFO {
+            LogLevel::Info
+        } else {
+            LogLevel::Debug
+        }
+    }
+}
+
+impl<S> Layer<S> for BeamLayer
+where S: Subscriber + for<'a> LookupSpan<'a> {
+    fn new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: layer::Context<'_, S>) {
+        let mut fields = Message::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(fields.fields));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: layer::Context<'_, S>) {
+        // Messages can't be sent from the BEAM's own threads, only from the runtime's.
+        if std::thread::current().name() != Some(POOL_THREAD_NAME) {
+            return
+        }
+        let handler = match HANDLER.read() {
+            Ok(handler) => handler,
+            Err(_err) => return,
+        };
+        let handler = match handler.as_ref() {
+            // More verbose levels compare greater.
+            Some(handler) if *event.metadata().level() <= handler.level => handler,
+            _ => return,
+        };
+        let mut message = Message::default();
+        event.record(&mut message);
+        // Each span the event happened in, outermost first, as "name{fields}".
+        let mut scope = Vec::new();
+        let mut span = ctx.lookup_current();
+        while let Some(current) = span {
+            let fields = current.extensions()
+                .get::<SpanFields>()
+                .map(|fields| fields.0.trim_start().to_string())
+                .unwrap_or_default();
+            scope.push(format!("{}{{{}}}", current.name(), fields));
+            span = current.parent();
+        }
+        scope.reverse();
+        let text = if scope.is_empty() {
+            format!("{}{}", message.message, message.fields)
+        } else {
+            format!("{}: {}{}", scope.join(":"), message.message, message.fields)
+        };
+        let level : LogLevel = event.metadata().level().into();
+        send_to(&handler.pid, (super::centaurus_log(), level, text)).ok();
+    }
+}
+
+impl Visit for Message {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message.push_str(value),
+            name => { write!(self.fields, " {}={}", name, value).ok(); },
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => { write!(self.message, "{:?}", value).ok(); },
+            name => { write!(self.fields, " {}={:?}", name, value).ok(); },
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Process monitors for the owners of sockets and streams.
+//! Rustler does not expose enif_monitor_process, so the monitor resource type is opened directly.
+
+use anyhow::{ Result };
+
+use rustler::{ Env, LocalPid };
+use rustler::sys::{
+    enif_alloc_resource,
+    enif_demonitor_process,
+    enif_monitor_process,
+    enif_open_resource_type_x,
+    enif_release_resource,
+    ErlNifEnv,
+    ErlNifMonitor,
+    ErlNifPid,
+    ErlNifResourceDown,
+    ErlNifResourceDtor,
+    ErlNifResourceFlags,
+    ErlNifResourceType,
+    ErlNifResourceTypeInit,
+};
+
+use std::{
+    ffi::{ c_void },
+    fmt,
+    mem::{ self, MaybeUninit },
+    ptr,
+    sync::atomic::{ AtomicPtr, Ordering },
+};
+
+static MONITOR_TYPE : AtomicPtr<ErlNifResourceType> = AtomicPtr::new(ptr::null_mut());
+
+// The resource object the Beam holds on to while the monitor is active.
+struct Owner {
+    on_down: Box<dyn Fn() + Send + Sync>,
+}
+
+/// An active monitor on the owning process. The monitor is removed when this is dropped.
+pub struct Monitor {
+    resource: *mut c_void,
+    monitor: ErlNifMonitor,
+}
+
+impl fmt::Debug for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Owner Monitor.")
+    }
+}
+
+// The resource is only touched through the thread safe enif functions.
+unsafe impl Send for Monitor {}
+unsafe impl Sync for Monitor {}
+
+impl Monitor {
+    /// Monitors the process, calling on_down once if the process exits.
+    pub fn new<F>(pid: &LocalPid, on_down: F) -> Result<Self>
+    where F: Fn() + Send + Sync + 'static {
+        let resource_type = MONITOR_TYPE.load(Ordering::SeqCst);
+        if resource_type.is_null() {
+            return Err(anyhow::anyhow!("Monitor resource type not loaded."));
+        }
+        unsafe {
+            let resource = enif_alloc_resource(resource_type, mem::size_of::<Owner>());
+            ptr::write(resource as *mut Owner, Owner { on_down: Box::new(on_down) });
+            let mut monitor = MaybeUninit::<ErlNifMonitor>::zeroed();
+            let result = enif_monitor_process(ptr::null_mut(), resource, pid.as_c_arg(), monitor.as_mut_ptr());
+            if result != 0 {
+                enif_release_resource(resource);
+                return Err(anyhow::anyhow!("Owner process is not alive."));
+            }
+            Ok(Monitor {
+                resource,
+                monitor: monitor.assume_init(),
+            })
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        unsafe {
+            enif_demonitor_process(ptr::null_mut(), self.resource, &self.monitor);
+            enif_release_resource(self.resource);
+        }
+    }
+}
+
+unsafe extern "C" fn owner_down(_env: *mut ErlNifEnv, obj: *mut c_void, _pid: *const ErlNifPid, _mon: *const ErlNifMonitor) {
+    let owner = &*(obj as *const Owner);
+    (owner.on_down)();
+}
+
+unsafe extern "C" fn owner_dtor(_env: *mut ErlNifEnv, obj: *mut c_void) {
+    ptr::drop_in_place(obj as *mut Owner);
+}
+
+/// Opens the resource type used for monitors. Called when the Nif is loaded.
+pub fn open_resource_type(env: Env) -> bool {
+    let init = ErlNifResourceTypeInit {
+        dtor: owner_dtor as *const ErlNifResourceDtor,
+        stop: ptr::null(),
+        down: owner_down as *const ErlNifResourceDown,
+    };
+    let mut tried = ErlNifResourceFlags::ERL_NIF_RT_CREATE;
+    let resource_type = unsafe {
+        enif_open_resource_type_x(
+            env.as_c_arg(),
+            b"Centaurus.Monitor\0".as_ptr(),
+            &init,
+            ErlNifResourceFlags::ERL_NIF_RT_CREATE,
+            &mut tried,
+        )
+    };
+    MONITOR_TYPE.store(resource_type as *mut ErlNifResourceType, Ordering::SeqCst);
+    !resource_type.is_null()
+}
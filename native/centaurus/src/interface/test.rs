@@ -14,6 +14,7 @@ use super::types::{
     Stream
 };
 
+use crate::conn::{ queue, Capacity, ControlEvent, Handle };
 use crate::error::{ ApplicationError };
 use crate::options::{ QuicOptions };
 
@@ -30,7 +31,8 @@ use std::{
     io::{ Write },
     fs::{ File },
     ops::{ Deref },
-    path::PathBuf
+    path::PathBuf,
+    sync::{ Arc },
 };
 
 type Result<T> = std::result::Result<T, Error>;
@@ -101,10 +103,18 @@ fn test_socket_config(socket: BeamSocket) -> Result<BeamSocket> {
     Ok(BeamSocket {
         socket_pid: socket.socket_pid,
         bind_address: Some(SocketAddr("127.0.0.1:0".parse().unwrap())),
+        fd: None,
         server_name: "localhost".to_string(),
-        options: QuicOptions { timeout: None },
+        options: QuicOptions::default(),
         private_key: Some(PrivateKey(PathBuf::from("/"))),
-        certificates: Some(Certificates(PathBuf::from("/")))
+        certificates: Some(Certificates(PathBuf::from("/"))),
+        trusted_certificates: None,
+        stream_acceptor: None,
+        admission: None,
+        retry: None,
+        reset_key: None,
+        cid_length: None,
+        telemetry_pid: None,
     })
 }
 
@@ -113,7 +123,8 @@ fn test_stream_config(stream: BeamStream) -> Result<BeamStream> {
     Ok(BeamStream {
         stream_pid: stream.stream_pid,
         stream_type: StreamType::Bi,
-        options: QuicOptions { timeout: None }
+        options: QuicOptions::default(),
+        priority: None,
     })
 }
 
@@ -127,16 +138,21 @@ fn test_stream(stream: Stream) -> Result<Stream> {
     Ok(stream)
 }
 
+// A handle with no task behind it. Calls on it fail as the runtime task closed.
+fn detached_handle<T: ControlEvent>() -> Arc<Handle<T>> {
+    let (sender, _receiver) = queue(1);
+    let (control, _control) = unbounded_channel();
+    Arc::new(Handle::new(sender, control, Arc::new(Capacity::new(1))))
+}
+
 #[rustler::nif]
 fn get_socket() -> Result<Socket> {
-    let (sender, _receiver) = unbounded_channel();
-    Ok(Socket::from(crate::conn::Socket(sender)))
+    Ok(Socket::from(crate::conn::Socket(detached_handle())))
 }
 
 #[rustler::nif]
 fn get_stream() -> Result<Stream> {
-    let (sender, _receiver) = unbounded_channel();
-    Ok(Stream::from(crate::conn::Stream(sender)))
+    Ok(Stream::from(crate::conn::Stream(detached_handle())))
 }
 
 #[rustler::nif]
@@ -184,3 +200,8 @@ fn test_quic_stream(pid: QuicStream) -> Result<QuicStream> {
     Ok(pid)
 }
 
+
+#[rustler::nif]
+fn live_tasks() -> Result<usize> {
+    Ok(crate::runtime::live_tasks())
+}
@@ -5,6 +5,7 @@ use crate::options::{ QuicOptions };
 use rustler::{
     LocalPid,
     ResourceArc,
+    NifMap,
     NifStruct,
     NifUnitEnum,
     NifUntaggedEnum,
@@ -15,12 +16,40 @@ use std::{
     ops::{ Deref },
     path::{ PathBuf },
     sync::{ Arc },
+    time::{ Duration },
 };
 
-/// "127.0.0.1:8080" on the Elixir side.
+/// "127.0.0.1:8080" or {{127, 0, 0, 1}, 8080} on the Elixir side. Encoded as the tuple.
 #[derive(Debug, Copy, Clone)]
 pub struct SocketAddr(pub std::net::SocketAddr);
 
+/// The address of a peer to connect to. Any SocketAddr, "example.com:8080" or
+/// {"example.com", 8080} on the Elixir side.
+#[derive(Debug, Clone)]
+pub enum PeerAddr {
+    Addr(std::net::SocketAddr),
+    Host(String, u16),
+}
+
+/// A timeout in milliseconds or :infinity. nil falls back to the timeout in the config's options.
+#[derive(Debug, Copy, Clone)]
+pub enum Timeout {
+    Default,
+    Infinity,
+    Millis(u64),
+}
+
+impl Timeout {
+    /// The time to wait given the configured default in milliseconds. None waits forever.
+    pub fn or(self, default: Option<u64>) -> Option<Duration> {
+        match self {
+            Timeout::Default => default.map(Duration::from_millis),
+            Timeout::Infinity => None,
+            Timeout::Millis(millis) => Some(Duration::from_millis(millis)),
+        }
+    }
+}
+
 impl Deref for SocketAddr {
     type Target = std::net::SocketAddr;
     
@@ -29,33 +58,142 @@ impl Deref for SocketAddr {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PrivateKey(pub PathBuf);
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Certificates(pub PathBuf);
 
+/// The path of a file holding the key that signs Retry tokens.
+#[derive(Clone, Debug)]
+pub struct TokenKey(pub PathBuf);
+
+/// The path of a file holding the key that derives stateless reset tokens.
+#[derive(Clone, Debug)]
+pub struct ResetKey(pub PathBuf);
+
 #[derive(NifStruct)]
 #[module="Centaurus.Types.SocketConfig"]
 #[rustler(encode, decode)]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BeamSocket {
     pub socket_pid: Option<QuicSocket>,
     pub bind_address: Option<SocketAddr>,
+    pub fd: Option<i32>,
     pub server_name: String,
     pub options: QuicOptions,
     pub private_key: Option<PrivateKey>,
     pub certificates: Option<Certificates>,
+    pub trusted_certificates: Option<Certificates>,
+    pub stream_acceptor: Option<StreamAcceptor>,
+    pub admission: Option<Admission>,
+    pub retry: Option<Retry>,
+    pub reset_key: Option<ResetKey>,
+    pub cid_length: Option<usize>,
+    pub telemetry_pid: Option<QuicSocket>,
 }
 
 #[derive(NifStruct)]
 #[module="Centaurus.Types.StreamConfig"]
 #[rustler(encode, decode)]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BeamStream {
     pub stream_pid: Option<QuicStream>,
     pub stream_type: StreamType,
     pub options: QuicOptions,
+    pub priority: Option<i32>,
+}
+
+/// Decides who receives the streams opened by the peer.
+/// Active streams are sent to the pids for the stream type in turn, or to the socket's owner
+/// when there are none. Passive streams wait for accept_stream.
+#[derive(NifStruct)]
+#[module="Centaurus.Types.StreamAcceptor"]
+#[rustler(encode, decode)]
+#[derive(Clone, Debug)]
+pub struct StreamAcceptor {
+    pub mode: AcceptMode,
+    pub bi: Vec<QuicStream>,
+    pub uni: Vec<QuicStream>,
+}
+
+#[derive(NifUnitEnum)]
+#[rustler(encode, decode)]
+#[derive(Clone, Copy, Debug)]
+pub enum AcceptMode {
+    Active,
+    Passive,
+}
+
+/// Limits the connections a listener accepts.
+/// max_connections counts the listener's connections in their handshake, waiting for an accept
+/// and accepted but not yet closed.
+/// max_per_ip connections are allowed from each address every rate_window milliseconds.
+/// Addresses in deny are refused, and when allow is not empty only addresses in it are accepted.
+#[derive(NifStruct)]
+#[module="Centaurus.Types.Admission"]
+#[rustler(encode, decode)]
+#[derive(Clone, Debug)]
+pub struct Admission {
+    pub max_connections: Option<u32>,
+    pub max_per_ip: Option<u32>,
+    pub rate_window: Option<u64>,
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+/// Address validation for a listener's new connections.
+/// token_lifetime is in milliseconds. Without a token_key a random key is used, so tokens don't
+/// outlive the listener.
+#[derive(NifStruct)]
+#[module="Centaurus.Types.Retry"]
+#[rustler(encode, decode)]
+#[derive(Clone, Debug)]
+pub struct Retry {
+    pub mode: RetryMode,
+    pub token_key: Option<TokenKey>,
+    pub token_lifetime: Option<u64>,
+}
+
+/// quinn either sends a Retry to every new client or to none of them.
+#[derive(NifUnitEnum)]
+#[rustler(encode, decode)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetryMode {
+    Never,
+    Always,
+}
+
+/// %{accepted: 0, rate_limited: 0, denied: 0, at_capacity: 0} on the Elixir side.
+#[derive(NifMap)]
+#[rustler(encode)]
+pub struct AdmissionStats {
+    pub accepted: u64,
+    pub rate_limited: u64,
+    pub denied: u64,
+    pub at_capacity: u64,
+}
+
+/// The runtime's counters, as a map such as %{connections_opened: 0, bytes_read: 0, ...}.
+#[derive(NifMap)]
+#[rustler(encode)]
+pub struct Metrics {
+    pub connections_opened: u64,
+    pub connections_closed: u64,
+    pub handshake_failures: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+/// The Logger levels the runtime's log events are forwarded at.
+#[derive(NifUnitEnum)]
+#[rustler(encode, decode)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
 }
 
 #[derive(NifUnitEnum)]
@@ -64,11 +202,13 @@ pub struct BeamStream {
 pub enum SocketType {
     Server,
     Client,
+    // Listens and connects from the same endpoint.
+    Peer,
 }
 
 #[derive(NifUnitEnum)]
 #[rustler(encode, decode)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum ConnectionOwner {
     Peer,
     Host,
@@ -76,7 +216,7 @@ pub enum ConnectionOwner {
 
 #[derive(NifUnitEnum)]
 #[rustler(encode, decode)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum StreamType {
     Bi,
     Uni,
@@ -99,8 +239,19 @@ impl Deref for Error {
     }
 }
 
+/// The result of a call with nothing to return. Encodes as :ok or {:error, reason}.
+#[derive(Debug)]
+pub struct Status(pub Result<(), Error>);
+
+impl From<anyhow::Result<()>> for Status {
+    fn from(result: anyhow::Result<()>) -> Self {
+        Status(result.map_err(Error))
+    }
+}
+
 #[derive(NifUntaggedEnum)]
 #[rustler(encode, decode)]
+#[derive(Clone)]
 pub enum QuicStream {
     Pid(LocalPid)
 }
@@ -113,6 +264,7 @@ impl fmt::Debug for QuicStream {
 
 #[derive(NifUntaggedEnum)]
 #[rustler(encode, decode)]
+#[derive(Clone)]
 pub enum QuicSocket{
     Pid(LocalPid)
 }
@@ -156,6 +308,72 @@ pub struct SocketInterior(conn::Socket);
 pub enum Stream { Stream(ResourceArc::<StreamInterior>) }
 pub struct StreamInterior(conn::Stream);
 
+/// A reference on the Elixir side that cancels the call it's given to.
+#[derive(NifUntaggedEnum)]
+#[rustler(encode, decode)]
+#[derive(Clone)]
+pub enum Operation { Operation(ResourceArc::<OperationInterior>) }
+pub struct OperationInterior(conn::Operation);
+
+/// %{id: 0, direction: :bi, initiator: :host, socket: socket} on the Elixir side.
+/// The socket is nil once the connection has been dropped.
+#[derive(NifMap)]
+#[rustler(encode)]
+pub struct StreamInfo {
+    pub id: u64,
+    pub direction: StreamType,
+    pub initiator: ConnectionOwner,
+    pub socket: Option<Socket>,
+}
+
+/// The metadata of [:centaurus, :connection, :start] and [:centaurus, :connection, :stop].
+/// The socket is the connection's id, nil once it has been dropped, and the reason is only set
+/// on stop.
+#[derive(NifMap)]
+#[rustler(encode)]
+pub struct ConnectionMetadata {
+    pub socket: Option<u64>,
+    pub peer: Option<SocketAddr>,
+    pub reason: Option<String>,
+}
+
+/// The metadata of [:centaurus, :stream, :open] and [:centaurus, :stream, :close]. As StreamInfo,
+/// with the connection's id in place of the socket.
+#[derive(NifMap)]
+#[rustler(encode)]
+pub struct StreamMetadata {
+    pub id: u64,
+    pub direction: StreamType,
+    pub initiator: ConnectionOwner,
+    pub socket: Option<u64>,
+}
+
+/// Any of the handles the runtime's messages name by id.
+#[derive(NifUntaggedEnum)]
+#[rustler(encode, decode)]
+pub enum Identified {
+    NewSocket(NewSocket),
+    Socket(Socket),
+    Stream(Stream),
+}
+
+/// Any of the handles that can be owned by a process.
+#[derive(NifUntaggedEnum)]
+#[rustler(encode, decode)]
+pub enum Owned {
+    NewSocket(NewSocket),
+    Socket(Socket),
+    Stream(Stream),
+}
+
+/// Either of the handles that hold an endpoint.
+#[derive(NifUntaggedEnum)]
+#[rustler(encode, decode)]
+pub enum Bound {
+    NewSocket(NewSocket),
+    Socket(Socket),
+}
+
 impl Deref for NewSocket {
     type Target = NewSocketInterior;
     
@@ -259,6 +477,21 @@ impl From<conn::Socket> for SocketInterior {
     }
 }
 
+impl From<conn::Operation> for Operation {
+    fn from(operation : conn::Operation) -> Self {
+        Operation::Operation(ResourceArc::new(OperationInterior(operation)))
+    }
+}
+
+impl Deref for Operation {
+    type Target = conn::Operation;
+
+    fn deref(&self) -> &Self::Target {
+        let Operation::Operation(operation) = self;
+        &operation.0
+    }
+}
+
 impl From<conn::Stream> for Stream {
     fn from(stream_int : conn::Stream) -> Self {
         Stream::Stream(ResourceArc::new(stream_int.into()))
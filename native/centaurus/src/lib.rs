@@ -1,10 +1,12 @@
 #![feature(try_trait)]
 #![feature(async_closure)]
 
+mod admission;
 mod config;
 mod conn;
 mod error;
 mod interface;
+mod metrics;
 mod options;
 mod runtime;
 mod state;
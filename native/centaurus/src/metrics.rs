@@ -0,0 +1,42 @@
+//! Counts connections and traffic across every socket on the runtime.
+
+use crate::interface::types::{ Metrics };
+
+use std::sync::atomic::{ AtomicU64, Ordering };
+
+static CONNECTIONS_OPENED : AtomicU64 = AtomicU64::new(0);
+static CONNECTIONS_CLOSED : AtomicU64 = AtomicU64::new(0);
+static HANDSHAKE_FAILURES : AtomicU64 = AtomicU64::new(0);
+static BYTES_READ : AtomicU64 = AtomicU64::new(0);
+static BYTES_WRITTEN : AtomicU64 = AtomicU64::new(0);
+
+pub fn connection_opened() {
+    CONNECTIONS_OPENED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn connection_closed() {
+    CONNECTIONS_CLOSED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn handshake_failed() {
+    HANDSHAKE_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn bytes_read(count: usize) {
+    BYTES_READ.fetch_add(count as u64, Ordering::Relaxed);
+}
+
+pub fn bytes_written(count: usize) {
+    BYTES_WRITTEN.fetch_add(count as u64, Ordering::Relaxed);
+}
+
+/// The counters as they are now. The counters only ever go up.
+pub fn snapshot() -> Metrics {
+    Metrics {
+        connections_opened: CONNECTIONS_OPENED.load(Ordering::Relaxed),
+        connections_closed: CONNECTIONS_CLOSED.load(Ordering::Relaxed),
+        handshake_failures: HANDSHAKE_FAILURES.load(Ordering::Relaxed),
+        bytes_read: BYTES_READ.load(Ordering::Relaxed),
+        bytes_written: BYTES_WRITTEN.load(Ordering::Relaxed),
+    }
+}
@@ -1,10 +1,40 @@
 /// The set of options, defaults, and related checking functions.
 //use rustler::{ NifUntaggedEnum };
+use crate::error::{ ApplicationError };
 
-#[derive(Debug)]
+use quinn::{ VarInt };
+
+// The number of operations a socket or stream queues when queue_capacity isn't set.
+const DEFAULT_QUEUE_CAPACITY : usize = 64;
+
+#[derive(Clone, Debug, Default)]
 #[derive(NifStruct)]
 #[module = "Centaurus.Types.Options"]
 #[rustler(encode, decode)]
 pub struct QuicOptions {
     pub timeout: Option<u64>,
+    pub close_code: Option<ApplicationError>,
+    pub queue_capacity: Option<usize>,
+    pub reuse_port: Option<bool>,
+    pub recv_buffer: Option<usize>,
+    pub send_buffer: Option<usize>,
+    pub ipv6_only: Option<bool>,
+    pub idle_timeout: Option<u64>,
+    pub keep_alive: Option<u64>,
+}
+
+impl QuicOptions {
+    /// The error code used when a socket or stream is closed because its handle was dropped.
+    /// Defaults to 0 (no error).
+    pub fn close_code(&self) -> VarInt {
+        self.close_code
+            .map(|code| code.into())
+            .unwrap_or_else(|| VarInt::from_u32(0))
+    }
+
+    /// The number of operations that can be queued or running on the socket or stream before
+    /// new ones are turned away as busy. Defaults to 64.
+    pub fn queue_capacity(&self) -> usize {
+        self.queue_capacity.unwrap_or(DEFAULT_QUEUE_CAPACITY)
+    }
 }
@@ -1,41 +1,75 @@
 //! Provides traits and types for working with the Tokio runtime.
+use crate::admission::{ AdmissionControl, AdmissionCounters };
 use crate::config::{ Configs };
-use crate::conn::{ NewSocket, Socket, Stream };
-use crate::error::{ ApplicationError };
+use crate::conn::{ queue, Capacity, ControlEvent, Handle, NewSocket, Queued, Slot, Socket, Stream };
+use crate::error::{ ApplicationError, Busy, Cancelled };
+use crate::metrics;
 use crate::state::{ SocketState, StreamState };
-use crate::interface::types::{ SocketType };
+use crate::interface::{
+    config_impl::{ send_to },
+    monitor::{ Monitor },
+    types::{ ConnectionMetadata, ConnectionOwner, PeerAddr, QuicStream, SocketType, StreamInfo, StreamMetadata, StreamType, Timeout },
+};
 
 use anyhow::{ Context, Result };
 
 use either::{ Either };
 
+use rustler::{ Atom, Encoder, LocalPid };
+
+use bytes::{ Bytes };
+
+use futures::{
+    future,
+    stream::{ FuturesUnordered },
+};
+
 use quinn::{
+    Connecting,
+    ConnectionError,
+    Dir,
+    Endpoint,
     EndpointBuilder,
+    Incoming,
     IncomingBiStreams,
     IncomingUniStreams,
-    OpenBi,
-    OpenUni
+    NewConnection,
+    RecvStream,
+    SendStream,
+    VarInt,
 };
 
+use tracing::{ debug, info, info_span, warn, Instrument, Span };
+
 use tokio::runtime;
 use tokio::{
+    net,
     runtime::{ Builder },
     stream::{ StreamExt },
     sync::{
         mpsc::{
+            channel as queue_channel,
+            error::{ TrySendError },
             unbounded_channel,
+            Receiver as QueueReceiver,
+            Sender as QueueSender,
             UnboundedReceiver as AsyncReceiver,
             UnboundedSender as AsyncSender,
         },
         Mutex,
+        oneshot,
     },
-    time,
+    time::{ self, Instant },
 };
 
 use std::{
+    collections::{ VecDeque },
     fmt,
-    net::{ SocketAddr },
-    sync::{ Arc, Once },
+    future::{ Future },
+    net::{ IpAddr, SocketAddr },
+    pin::{ Pin },
+    sync::{ Arc, Once, Weak },
+    sync::atomic::{ AtomicU64, AtomicUsize, Ordering },
     sync::mpsc::{ Sender },
     thread::{ JoinHandle },
     time::{ Duration },
@@ -43,11 +77,42 @@ use std::{
 
 type Responder<T> = Mutex<Sender<T>>;
 
+// Quinn doesn't report migrations, so connections check the peer's address this often.
+const PEER_ADDRESS_INTERVAL : Duration = Duration::from_secs(1);
+
+// How long a connection attempt gets before the next address is tried alongside it.
+const CONNECT_ATTEMPT_DELAY : Duration = Duration::from_millis(250);
+
+// How often a shutting down listener checks whether its connections have closed.
+const DRAIN_INTERVAL : Duration = Duration::from_millis(50);
+type ReadRequest = (Responder<Result<usize>>, Arc<Mutex<Vec<u8>>>, Option<Duration>, Option<Cancel>, Option<Slot<StreamEvent>>);
+
+/// Fires when the call waiting on it is cancelled.
+pub type Cancel = oneshot::Receiver<()>;
+
+// Requests for the sending half's task. A priority change takes its turn behind the writes
+// queued before it, as the send stream is held for the whole of a write.
+enum WriteRequest {
+    Write(Responder<Result<()>>, Vec<u8>, Option<Duration>, Option<Slot<StreamEvent>>),
+    Priority(Responder<Result<()>>, i32, Option<Slot<StreamEvent>>),
+}
+
+impl WriteRequest {
+    fn into_responder(self) -> Responder<Result<()>> {
+        match self {
+            WriteRequest::Write(responder, ..) | WriteRequest::Priority(responder, ..) => responder,
+        }
+    }
+}
+
+/// The name of the runtime's worker threads.
+pub const POOL_THREAD_NAME : &str = "centaurus-pool";
+
 /// Starts a new tokio runtime.
 /// The runtime is a threaded pool named "centaurus-pool".
 fn new_pool() -> runtime::Runtime {
     Builder::new()
-        .thread_name("centaurus-pool")
+        .thread_name(POOL_THREAD_NAME)
         .threaded_scheduler()
         .enable_all()
         .build()
@@ -57,6 +122,109 @@ fn new_pool() -> runtime::Runtime {
 // The main tokio runtime.
 struct RuntimeInternal(runtime::Runtime);
 
+// The number of socket and stream tasks currently running on the runtime.
+static LIVE_TASKS : AtomicUsize = AtomicUsize::new(0);
+
+// Counts a task as live for as long as the guard is held.
+struct TaskGuard;
+
+impl TaskGuard {
+    fn new() -> Self {
+        LIVE_TASKS.fetch_add(1, Ordering::SeqCst);
+        TaskGuard
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        LIVE_TASKS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Returns the number of socket and stream tasks still running.
+pub fn live_tasks() -> usize {
+    LIVE_TASKS.load(Ordering::SeqCst)
+}
+
+// Spawns a socket or stream task that is tracked by LIVE_TASKS.
+fn spawn<F>(task: F)
+where F: Future<Output = ()> + Send + 'static {
+    let guard = TaskGuard::new();
+    tokio::spawn(async move {
+        task.await;
+        drop(guard);
+    });
+}
+
+// Numbers endpoints and connections so their log events can be told apart.
+static NEXT_SPAN_ID : AtomicU64 = AtomicU64::new(0);
+
+fn endpoint_span(socket_type: &SocketType) -> Span {
+    info_span!("endpoint", id = NEXT_SPAN_ID.fetch_add(1, Ordering::Relaxed), kind = ?socket_type)
+}
+
+fn connection_span() -> Span {
+    info_span!("connection", id = NEXT_SPAN_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+// Streams already have an id of their own.
+fn stream_span(stream: &StreamRuntimeLocal) -> Span {
+    match stream.runtime.id() {
+        Ok(id) => info_span!("stream", id),
+        Err(_err) => info_span!("stream"),
+    }
+}
+
+// Logs the failure of a step nobody is waiting on the result of.
+fn log_failure(step: &str, result: Result<()>) {
+    if let Err(error) = result {
+        warn!("{} failed: {:#}", step, error);
+    }
+}
+
+// Runs the operation, failing with the message if it takes longer than the timeout.
+async fn within<T, F>(timeout: Option<Duration>, message: &'static str, operation: F) -> Result<T>
+where F: Future<Output = Result<T>> {
+    match timeout {
+        Some(timeout) => time::timeout(timeout, operation)
+            .await
+            .unwrap_or_else(|_elapsed| Err(anyhow::anyhow!(message))),
+        None => operation.await,
+    }
+}
+
+// Runs the operation until it finishes or is cancelled. Dropping the operation's handle
+// without cancelling leaves it running.
+async fn cancellable<T, F>(cancel: Option<Cancel>, operation: F) -> Result<T>
+where F: Future<Output = Result<T>> {
+    match cancel {
+        Some(cancel) => tokio::select! {
+            result = operation => result,
+            Ok(()) = cancel => Err(Cancelled.into()),
+        },
+        None => operation.await,
+    }
+}
+
+// Waits for the next event on a socket or stream, with the place it holds in the queue. The
+// runtime's own events don't hold a place.
+async fn next_event<T: ControlEvent>(queue: &mut QueueReceiver<Queued<T>>, control: &mut AsyncReceiver<T>) -> (Option<T>, Option<Slot<T>>) {
+    tokio::select! {
+        Some(event) = control.recv() => (Some(event), None),
+        queued = queue.recv() => match queued {
+            Some(Queued { event, slot }) => (Some(event), slot),
+            None => (None, None),
+        },
+    }
+}
+
+// Tells the owner of a socket or stream that turned a call away as busy that it has room again.
+fn notify_ready<M: Encoder>(owner: Option<LocalPid>, msg: Option<M>) {
+    if let (Some(pid), Some(msg)) = (owner, msg) {
+        send_to(&pid, msg).ok();
+    }
+}
+
 #[derive(Clone, Debug)]
 // State of the socket on the runtime.
 struct SocketRuntime {
@@ -65,14 +233,19 @@ struct SocketRuntime {
 }
 
 impl SocketRuntime {
+    // The timeout to wait for an operation on the socket, falling back to the configured one.
+    async fn timeout(&self, timeout: Timeout) -> Option<Duration> {
+        timeout.or(self.configs.socket_config.read().await.options.timeout)
+    }
+
     async fn new(configs: Configs, builder: EndpointBuilder) -> Result<Self> {
-        let bind_addr = configs
+        let udp_socket = configs
             .socket_config
             .read()
             .await
-            .address()
-            .context("Unable to get bind address.")?;
-        builder.bind(&bind_addr)
+            .udp_socket()
+            .context("Unable to open socket.")?;
+        builder.with_socket(udp_socket)
             .map(|state| {
                 Self {
                     configs,
@@ -83,37 +256,299 @@ impl SocketRuntime {
     }
 }
 
-#[derive(Debug)]
 struct NewSocketRuntimeLocal {
-    receiver: AsyncReceiver<NewSocketEvent>,
+    receiver: QueueReceiver<Queued<NewSocketEvent>>,
+    control: AsyncReceiver<NewSocketEvent>,
+    sender: AsyncSender<NewSocketEvent>,
+    handle: Weak<Handle<NewSocketEvent>>,
+    owner: Option<Monitor>,
+    admission: AdmissionControl,
+    counters: Arc<AdmissionCounters>,
+    // The clients arriving at the listener. Taken from the endpoint's state so the task can
+    // start each handshake as soon as its client arrives, whether or not an accept is waiting.
+    incoming: Option<Incoming>,
+    // Connections still in their handshake.
+    handshakes: FuturesUnordered<Handshaking>,
+    // Connections that finished their handshake and are waiting for an accept.
+    established: VecDeque<Established>,
+    // Accept calls waiting for a connection.
+    waiting: VecDeque<AcceptWaiter>,
+    // The connections this listener has accepted, so a shutdown can drain them.
+    accepted: Vec<Accepted>,
     runtime: SocketRuntime,
 }
 
+// A finished handshake, with how long it took from when the client arrived.
+struct Handshake {
+    peer: SocketAddr,
+    duration: Duration,
+    result: std::result::Result<NewConnection, ConnectionError>,
+}
+
+type Handshaking = Pin<Box<dyn Future<Output = Handshake> + Send>>;
+
+struct Established {
+    connection: NewConnection,
+    handshake: Duration,
+}
+
+#[derive(Debug)]
+struct AcceptWaiter {
+    responder: Responder<Result<Socket>>,
+    owner: LocalPid,
+    deadline: Option<Instant>,
+    cancel: Option<Cancel>,
+    // The call keeps its place in the listener's queue while it waits.
+    _slot: Option<Slot<NewSocketEvent>>,
+}
+
+impl fmt::Debug for NewSocketRuntimeLocal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "New Socket Runtime.")
+    }
+}
+
+impl fmt::Debug for Established {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Established Connection.")
+    }
+}
+
+#[derive(Debug)]
+struct Accepted {
+    socket: Weak<Handle<SocketEvent>>,
+    running: Weak<()>,
+}
+
+impl Accepted {
+    fn open(&self) -> bool {
+        self.running.strong_count() > 0
+    }
+}
+
 impl NewSocketRuntimeLocal {
-    fn new(runtime: SocketRuntime) -> (AsyncSender<NewSocketEvent>, Self) {
-        let (sender, receiver) = unbounded_channel();
+    async fn new(runtime: SocketRuntime) -> Result<(NewSocket, Self)> {
+        let (queue_capacity, admission) = {
+            let socket_config = runtime.configs.socket_config.read().await;
+            (socket_config.options.queue_capacity(), AdmissionControl::new(socket_config.admission.as_ref())?)
+        };
+        let incoming = match runtime.state.local.as_ref() {
+            Some(local) => local.lock().await.incoming.take(),
+            None => None,
+        };
+        let (sender, receiver) = queue(queue_capacity);
+        let (control_sender, control) = unbounded_channel();
+        let counters = Arc::new(AdmissionCounters::default());
+        let handle = Arc::new(Handle::new(sender, control_sender.clone(), Arc::new(Capacity::new(queue_capacity))));
         let new_socket = Self {
             receiver,
+            control,
+            sender: control_sender,
+            handle: Arc::downgrade(&handle),
+            owner: None,
+            admission,
+            counters: counters.clone(),
+            incoming,
+            handshakes: FuturesUnordered::new(),
+            established: VecDeque::new(),
+            waiting: VecDeque::new(),
+            accepted: Vec::new(),
             runtime,
         };
-        (sender, new_socket)
+        Ok((NewSocket(handle, counters), new_socket))
+    }
+
+    // The connections the listener is handling, counted against max_connections.
+    fn open_connections(&mut self) -> usize {
+        self.accepted.retain(Accepted::open);
+        self.handshakes.len() + self.established.len() + self.accepted.len()
+    }
+
+    // The earliest time a waiting accept times out.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.waiting
+            .iter()
+            .filter_map(|waiter| waiter.deadline)
+            .min()
+    }
+
+    // Removes and returns the accepts that have timed out.
+    fn expired(&mut self, now: Instant) -> Vec<AcceptWaiter> {
+        let (done, pending) : (VecDeque<_>, VecDeque<_>) = self.waiting
+            .drain(..)
+            .partition(|waiter| waiter.deadline.map_or(false, |deadline| deadline <= now));
+        self.waiting = pending;
+        done.into_iter().collect()
+    }
+
+    // Tells the owner there is room in the queue again.
+    async fn ready(&self) {
+        let owner = self.runtime.configs.socket_config.read().await.owner();
+        let msg = self.handle
+            .upgrade()
+            .map(|handle| (crate::interface::quic_ready(), handle.id()));
+        notify_ready(owner, msg);
+    }
+
+    // Monitors the owner of the socket so it stops accepting when the owner exits. Connections
+    // it already accepted have owners of their own.
+    async fn monitor_owner(&mut self) -> Result<()> {
+        self.owner = None;
+        let owner = self.runtime.configs.socket_config.read().await.owner();
+        if let Some(pid) = owner {
+            let sender = self.sender.clone();
+            let monitor = Monitor::new(&pid, move || {
+                sender.send(NewSocketEvent::OwnerDown).ok();
+            })?;
+            self.owner = Some(monitor);
+        }
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 struct SocketRuntimeLocal {
-    receiver: AsyncReceiver<SocketEvent>,
+    receiver: QueueReceiver<Queued<SocketEvent>>,
+    control: AsyncReceiver<SocketEvent>,
+    sender: AsyncSender<SocketEvent>,
+    // Used to hand out new references to the socket without keeping it alive.
+    handle: Weak<Handle<SocketEvent>>,
     runtime: SocketRuntime,
+    owner: Option<Monitor>,
+    peer_streams: PeerStreams,
+    // The last address seen for the peer.
+    peer_address: Option<SocketAddr>,
+    // Held while the connection's task runs. Listeners watch it to see the connection close.
+    running: Arc<()>,
+    // How long the handshake took, reported when the connection starts.
+    handshake: Duration,
+    // Held by each of the connection's streams, so a connection that is going away can tell
+    // when its streams are done.
+    streams: Arc<()>,
+    // Set once the connection is going away. It closes, with the given error if there is one,
+    // when its last stream is done.
+    going_away: bool,
+    drain_close: Option<(ApplicationError, Option<String>)>,
+    // Pings set with set_keep_alive, on top of the keep-alive quinn fixes at the start.
+    keep_alive: Option<time::Interval>,
 }
 
 impl SocketRuntimeLocal {
-    fn new(runtime: SocketRuntime) -> (AsyncSender<SocketEvent>, Self) {
-        let (sender, receiver) = unbounded_channel();
+    async fn new(runtime: SocketRuntime) -> (Socket, Self) {
+        let queue_capacity = runtime.configs.socket_config.read().await.options.queue_capacity();
+        let (queue_sender, receiver) = queue(queue_capacity);
+        let (sender, control) = unbounded_channel();
+        let handle = Arc::new(Handle::new(queue_sender, sender.clone(), Arc::new(Capacity::new(queue_capacity))));
         let new_socket = Self {
             receiver,
+            control,
+            sender,
+            handle: Arc::downgrade(&handle),
             runtime,
+            owner: None,
+            peer_streams: PeerStreams::default(),
+            peer_address: None,
+            running: Arc::new(()),
+            handshake: Duration::default(),
+            streams: Arc::new(()),
+            going_away: false,
+            drain_close: None,
+            keep_alive: None,
+        };
+        (Socket(handle), new_socket)
+    }
+
+    // Returns a new reference to the socket if the Elixir side still holds one.
+    fn socket(&self) -> Result<Socket> {
+        self.handle
+            .upgrade()
+            .map(Socket)
+            .ok_or_else(|| anyhow::anyhow!("Socket Dropped."))
+    }
+
+    // Tells the owner there is room in the queue again.
+    async fn ready(&self) {
+        let owner = self.runtime.configs.socket_config.read().await.owner();
+        let msg = self.socket()
+            .ok()
+            .map(|quic_socket| (crate::interface::quic_ready(), quic_socket.id()));
+        notify_ready(owner, msg);
+    }
+
+    // Monitors the owner of the socket so the connection is closed when the owner exits.
+    async fn monitor_owner(&mut self) -> Result<()> {
+        self.owner = None;
+        let owner = self.runtime.configs.socket_config.read().await.owner();
+        if let Some(pid) = owner {
+            let sender = self.sender.clone();
+            let monitor = Monitor::new(&pid, move || {
+                sender.send(SocketEvent::OwnerDown).ok();
+            })?;
+            self.owner = Some(monitor);
+        }
+        Ok(())
+    }
+}
+
+// Streams opened by the peer that are waiting for accept_stream, and the accept_stream calls
+// waiting for a stream.
+#[derive(Debug, Default)]
+struct PeerStreams {
+    bi: VecDeque<StreamRuntime>,
+    uni: VecDeque<StreamRuntime>,
+    bi_waiting: VecDeque<StreamWaiter>,
+    uni_waiting: VecDeque<StreamWaiter>,
+    // The number of streams of each type handed out so far.
+    bi_count: usize,
+    uni_count: usize,
+}
+
+#[derive(Debug)]
+struct StreamWaiter {
+    responder: Responder<Result<Stream>>,
+    owner: QuicStream,
+    deadline: Option<Instant>,
+    // The call keeps its place in the socket's queue while it waits.
+    _slot: Option<Slot<SocketEvent>>,
+}
+
+impl PeerStreams {
+    fn queues(&mut self, stream_type: StreamType) -> (&mut VecDeque<StreamRuntime>, &mut VecDeque<StreamWaiter>) {
+        match stream_type {
+            StreamType::Bi => (&mut self.bi, &mut self.bi_waiting),
+            StreamType::Uni => (&mut self.uni, &mut self.uni_waiting),
+        }
+    }
+
+    fn next_count(&mut self, stream_type: StreamType) -> usize {
+        let count = match stream_type {
+            StreamType::Bi => &mut self.bi_count,
+            StreamType::Uni => &mut self.uni_count,
         };
-        (sender, new_socket)
+        *count += 1;
+        *count - 1
+    }
+
+    // The earliest time an accept_stream call times out.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.bi_waiting
+            .iter()
+            .chain(self.uni_waiting.iter())
+            .filter_map(|waiter| waiter.deadline)
+            .min()
+    }
+
+    // Removes and returns the accept_stream calls that have timed out.
+    fn expired(&mut self, now: Instant) -> Vec<StreamWaiter> {
+        let mut expired = Vec::new();
+        for waiting in vec![&mut self.bi_waiting, &mut self.uni_waiting] {
+            let (done, pending) : (VecDeque<_>, VecDeque<_>) = waiting
+                .drain(..)
+                .partition(|waiter| waiter.deadline.map_or(false, |deadline| deadline <= now));
+            *waiting = pending;
+            expired.extend(done);
+        }
+        expired
     }
 }
 
@@ -122,31 +557,163 @@ impl SocketRuntimeLocal {
 struct StreamRuntime {
     configs: Configs,
     state: StreamState,
+    initiator: ConnectionOwner,
+    // The connection the stream belongs to.
+    socket: Weak<Handle<SocketEvent>>,
+    // Keeps the connection's count of open streams.
+    _live: Arc<()>,
 }
 
 #[derive(Debug)]
 struct StreamRuntimeLocal {
-    receiver: AsyncReceiver<StreamEvent>,
+    receiver: QueueReceiver<Queued<StreamEvent>>,
+    control: AsyncReceiver<StreamEvent>,
+    sender: AsyncSender<StreamEvent>,
+    handle: Weak<Handle<StreamEvent>>,
     runtime: StreamRuntime,
+    owner: Option<Monitor>,
+    // The tasks driving each half of the stream. Reads and writes keep their place in the
+    // stream's queue until they finish, so their queues never hold more than its capacity.
+    reads: Option<Half<ReadRequest>>,
+    writes: Option<Half<WriteRequest>>,
+    queue_capacity: usize,
+}
+
+// The queue of the task running one half of a stream, and the signal that stops it.
+#[derive(Debug)]
+struct Half<R> {
+    requests: QueueSender<R>,
+    shutdown: oneshot::Sender<HalfShutdown>,
+}
+
+// How a half's task closes its side of the stream. Dropping the signal instead, as a split does,
+// stops the task and leaves the side open.
+#[derive(Clone, Copy, Debug)]
+enum HalfShutdown {
+    // Stops the receiving half or finishes the sending half with the code.
+    Close(VarInt),
+    // As Close, resetting the sending half with the code if it can't be finished.
+    Drop(VarInt),
+}
+
+impl HalfShutdown {
+    fn code(self) -> VarInt {
+        match self {
+            HalfShutdown::Close(code) | HalfShutdown::Drop(code) => code,
+        }
+    }
 }
 
 impl StreamRuntime {
-    fn new(state: StreamState, configs: Configs) -> StreamRuntime {
+    // The timeout to wait for an operation on the stream, falling back to the configured one.
+    async fn timeout(&self, timeout: Timeout) -> Option<Duration> {
+        timeout.or(self.configs.stream_config.read().await.options.timeout)
+    }
+
+    fn new(state: StreamState, configs: Configs, initiator: ConnectionOwner, socket: Weak<Handle<SocketEvent>>, live: Arc<()>) -> StreamRuntime {
         StreamRuntime {
             configs,
             state,
+            initiator,
+            socket,
+            _live: live,
         }
     }
+
+    fn id(&self) -> Result<u64> {
+        self.state
+            .id
+            .map(|id| VarInt::from(id).into_inner())
+            .ok_or_else(|| anyhow::anyhow!("Stream not opened."))
+    }
+
+    fn info(&self) -> Result<StreamInfo> {
+        let direction = match self.state.id.map(|id| id.dir()) {
+            Some(Dir::Bi) => StreamType::Bi,
+            Some(Dir::Uni) => StreamType::Uni,
+            None => return Err(anyhow::anyhow!("Stream not opened.")),
+        };
+        Ok(StreamInfo {
+            id: self.id()?,
+            direction,
+            initiator: self.initiator,
+            socket: self.socket
+                .upgrade()
+                .map(|handle| Socket(handle).into()),
+        })
+    }
 }
 
 impl StreamRuntimeLocal {
-    fn new(runtime: StreamRuntime) -> (AsyncSender<StreamEvent>, Self) {
-        let (sender, receiver) = unbounded_channel();
-        let new_socket = Self {
+    async fn new(runtime: StreamRuntime) -> (Stream, Self) {
+        let queue_capacity = runtime.configs.stream_config.read().await.options.queue_capacity();
+        let (queue_sender, receiver) = queue(queue_capacity);
+        let (sender, control) = unbounded_channel();
+        let handle = Arc::new(Handle::new(queue_sender, sender.clone(), Arc::new(Capacity::new(queue_capacity))));
+        let new_stream = Self {
             receiver,
+            control,
+            sender,
+            handle: Arc::downgrade(&handle),
             runtime,
+            owner: None,
+            reads: None,
+            writes: None,
+            queue_capacity,
+        };
+        (Stream(handle), new_stream)
+    }
+
+    // Starts a task for each half of the stream. The tasks end once their Half is dropped or
+    // told to shut down.
+    fn start_halves(&mut self) {
+        if let Some(recv) = self.runtime.state.recv.clone() {
+            let (requests, receiver) = queue_channel(self.queue_capacity.max(1));
+            let (shutdown, shutdown_receiver) = oneshot::channel();
+            spawn(run_reads(recv, receiver, shutdown_receiver).instrument(Span::current()));
+            self.reads = Some(Half { requests, shutdown });
+        }
+        if let Some(send) = self.runtime.state.send.clone() {
+            let (requests, receiver) = queue_channel(self.queue_capacity.max(1));
+            let (shutdown, shutdown_receiver) = oneshot::channel();
+            spawn(run_writes(send, receiver, shutdown_receiver).instrument(Span::current()));
+            self.writes = Some(Half { requests, shutdown });
+        }
+    }
+
+    // Tells the half tasks to close their sides of the stream. A pending read or write is
+    // abandoned rather than waited on, so this never blocks the stream's task.
+    fn stop_halves(&mut self, shutdown: HalfShutdown) {
+        if let Some(reads) = self.reads.take() {
+            reads.shutdown.send(shutdown).ok();
+        }
+        if let Some(writes) = self.writes.take() {
+            writes.shutdown.send(shutdown).ok();
+        }
+    }
+
+    // Tells the owner there is room in the queue again.
+    async fn ready(&self) {
+        let owner = self.runtime.configs.stream_config.read().await.owner();
+        let msg = match (self.handle.upgrade(), self.runtime.id()) {
+            (Some(handle), Ok(id)) => Some((crate::interface::quic_ready(), handle.id(), id)),
+            _ => None,
         };
-        (sender, new_socket)
+        notify_ready(owner, msg);
+    }
+
+    // Monitors the owner of the stream so the stream is closed when the owner exits.
+    async fn monitor_owner(&mut self) -> Result<()> {
+        self.owner = None;
+        let owner = self.runtime.configs.stream_config.read().await.owner();
+        if let Some(pid) = owner {
+            let sender = self.sender.clone();
+            let monitor = Monitor::new(&pid, move || {
+                sender.send(StreamEvent::OwnerDown).ok();
+            })?;
+            self.owner = Some(monitor);
+        }
+        Ok(())
     }
 }
 
@@ -185,25 +752,60 @@ impl fmt::Debug for Event {
 #[derive(Debug)]
 // Events a non-connected socket knows how to handle. Spawns a new socket connection.
 pub enum NewSocketEvent {
-    Accept(Responder<Result<Socket>>, Option<Duration>),
-    Connect(Responder<Result<Socket>>, SocketAddr, Option<Duration>),
+    Accept(Responder<Result<Socket>>, LocalPid, Timeout, Option<Cancel>),
+    Connect(Responder<Result<Socket>>, PeerAddr, Timeout),
     Close(ApplicationError, Option<String>),
+    CloseEndpoint(ApplicationError, Option<String>),
+    ControllingProcess(Responder<Result<()>>, LocalPid),
+    Dropped,
+    LocalAddress(Responder<Result<SocketAddr>>),
+    OwnerDown,
+    Ready,
+    Rebind(Responder<Result<()>>, SocketAddr),
+    WaitIdle(Responder<Result<()>>, Timeout),
+    Shutdown(Responder<Result<usize>>, Timeout, Option<(ApplicationError, Option<String>)>),
 }
 
-#[derive(Debug)]
 // Events a connected socket knows how to handle.
 pub enum SocketEvent {
+    AcceptStream(Responder<Result<Stream>>, LocalPid, StreamType, Timeout),
     Close(ApplicationError, Option<String>),
-    OpenBiStream(Responder<Result<Stream>>),
-    OpenUniStream(Responder<Result<Stream>>),
+    ControllingProcess(Responder<Result<()>>, LocalPid),
+    Dropped,
+    GoAway(Option<(ApplicationError, Option<String>)>),
+    OwnerDown,
+    OpenBiStream(Responder<Result<Stream>>, LocalPid),
+    LocalAddress(Responder<Result<SocketAddr>>),
+    OpenUniStream(Responder<Result<Stream>>, LocalPid),
+    Ready,
+    Rebind(Responder<Result<()>>, SocketAddr),
+    SetKeepAlive(Responder<Result<()>>, u64),
 }
 
-#[derive(Debug)]
 // Events the stream knows how to handle.
 pub enum StreamEvent {
     CloseStream(ApplicationError),
-    Read(Responder<Result<usize>>, Arc<Mutex<Vec<u8>>>, Option<Duration>),
-    Write(Responder<Result<()>>, Vec<u8>),
+    ControllingProcess(Responder<Result<()>>, LocalPid),
+    Dropped,
+    Info(Responder<Result<StreamInfo>>),
+    OwnerDown,
+    Read(Responder<Result<usize>>, Arc<Mutex<Vec<u8>>>, Timeout, Option<Cancel>),
+    Ready,
+    SetPriority(Responder<Result<()>>, i32),
+    Split(Responder<Result<(Stream, Stream)>>),
+    Write(Responder<Result<()>>, Vec<u8>, Timeout),
+}
+
+impl fmt::Debug for SocketEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Socket Event.")
+    }
+}
+
+impl fmt::Debug for StreamEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stream Event.")
+    }
 }
 
 pub fn run(mut pool : AsyncReceiver<Event>) {
@@ -214,18 +816,19 @@ pub fn run(mut pool : AsyncReceiver<Event>) {
                 loop {
                     match pool.recv().await.unwrap() {
                         Event::OpenSocket(responder, conn_type, configs, state) => {
-                            let runtime = SocketRuntime::new(configs, state).await;
-                            match runtime {
-                                Ok(runtime) => {
-                                    let (sender, socket) = NewSocketRuntimeLocal::new(runtime);
-                                    // Send the socket's sender to the synchronous side.
+                            let new_socket = match SocketRuntime::new(configs, state).await {
+                                Ok(runtime) => NewSocketRuntimeLocal::new(runtime).await,
+                                Err(error) => Err(error),
+                            };
+                            match new_socket {
+                                Ok((new_socket, socket)) => {
+                                    // Send the socket's handle to the synchronous side.
                                     responder.into_inner()
-                                        .send(Ok(sender.into()))
+                                        .send(Ok(new_socket))
                                         .unwrap();
                                     // Spawn a new task to handle the socket.
-                                    tokio::spawn(async move {
-                                        run_new_socket(socket).await;
-                                    });
+                                    let span = endpoint_span(&conn_type);
+                                    spawn(run_new_socket(socket).instrument(span));
                                 },
                                 Err(error) => {
                                     responder.into_inner()
@@ -242,134 +845,465 @@ pub fn run(mut pool : AsyncReceiver<Event>) {
     });
 }
 
-// These actions create a new socket on the runtime.
+// These actions create a new socket on the runtime. Calls that wait on the network, such as
+// connect and wait_idle, run on tasks of their own so the listener keeps starting handshakes.
 async fn run_new_socket(mut socket: NewSocketRuntimeLocal) {
+    if let Err(error) = socket.monitor_owner().await {
+        warn!("could not monitor owner: {:#}", error);
+        log_failure("drop", drop_new(&mut socket).await);
+        return
+    }
     loop {
-        match socket.receiver.recv().await {
-            Some(NewSocketEvent::Accept(responder, Some(timeout))) => {
-                let result = time::timeout(timeout, accept(&socket))
-                    .await
-                    .unwrap_or_else(|_error| Err(anyhow::anyhow!("Accept Timeout.")));
-                responder.into_inner()
-                    .send(result)
-                    .ok();
+        let deadline = socket.next_deadline();
+        let timeout = time::delay_until(deadline.unwrap_or_else(Instant::now));
+        tokio::select! {
+            (event, slot) = next_event(&mut socket.receiver, &mut socket.control) => {
+                if local_new_socket_event(&mut socket, event, slot).await.is_none() {
+                    break
+                }
             },
-            Some(NewSocketEvent::Accept(responder, None)) => {
-                let result = accept(&socket).await;
-                responder.into_inner()
-                    .send(result)
-                    .ok();
-            }
-            Some(NewSocketEvent::Connect(responder, sock_addr, Some(timeout))) => {
-                let result = time::timeout(timeout, connect(&mut socket, sock_addr))
-                    .await
-                    .unwrap_or_else(|_error| Err(anyhow::anyhow!("Connect Timeout.")));
-                match result {
-                    Ok((sender, socket)) => {
-                        responder.into_inner()
-                            .send(Ok(sender.into()))
-                            .ok();
-                        run_socket(socket).await;
-                    },
-                    Err(err) => {
-                        responder.into_inner()
-                            .send(Err(err))
-                            .ok();
-                        break
-                    }
+            connecting = next_incoming(&mut socket.incoming) => {
+                match connecting {
+                    Some(connecting) => admit(&mut socket, connecting),
+                    None => socket.incoming = None,
+                }
+            },
+            Some(handshake) = socket.handshakes.next(), if !socket.handshakes.is_empty() => {
+                handshake_finished(&mut socket, handshake).await;
+            },
+            (index, cancelled) = next_cancelled(&mut socket.waiting, |waiter| waiter.cancel.as_mut()) => {
+                if !cancelled {
+                    // The operation was dropped without cancelling, so the accept keeps waiting.
+                    socket.waiting[index].cancel = None;
+                } else if let Some(waiter) = socket.waiting.remove(index) {
+                    waiter.responder.into_inner()
+                        .send(Err(Cancelled.into()))
+                        .ok();
+                }
+            },
+            _ = timeout, if deadline.is_some() => {
+                for waiter in socket.expired(Instant::now()) {
+                    waiter.responder.into_inner()
+                        .send(Err(anyhow::anyhow!("Accept Timeout.")))
+                        .ok();
                 }
             },
-            Some(NewSocketEvent::Connect(responder, sock_addr, None)) => {
-                let result = connect(&mut socket, sock_addr).await;
+        }
+        serve_accepts(&mut socket).await;
+    }
+    for waiter in socket.waiting.drain(..) {
+        waiter.responder.into_inner()
+            .send(Err(anyhow::anyhow!("Incoming Streams closed.")))
+            .ok();
+    }
+}
+
+async fn local_new_socket_event(socket: &mut NewSocketRuntimeLocal, event: Option<NewSocketEvent>, slot: Option<Slot<NewSocketEvent>>) -> Option<()> {
+    match event {
+        Some(NewSocketEvent::Accept(responder, pid, timeout, mut cancel)) => {
+            // An accept cancelled before it reached the runtime never waits.
+            match cancel.as_mut().map(|cancel| cancel.try_recv()) {
+                Some(Ok(())) => {
+                    responder.into_inner()
+                        .send(Err(Cancelled.into()))
+                        .ok();
+                    return Some(())
+                },
+                Some(Err(oneshot::error::TryRecvError::Closed)) => cancel = None,
+                _ => (),
+            }
+            let timeout = socket.runtime.timeout(timeout).await;
+            socket.waiting.push_back(AcceptWaiter {
+                responder,
+                owner: pid,
+                deadline: timeout.map(|timeout| Instant::now() + timeout),
+                cancel,
+                _slot: slot,
+            });
+            Some(())
+        },
+        Some(NewSocketEvent::Connect(responder, peer_addr, timeout)) => {
+            let runtime = socket.runtime.clone();
+            let timeout = runtime.timeout(timeout).await;
+            tokio::spawn(async move {
+                let result = within(timeout, "Connect Timeout.", connect(&runtime, peer_addr)).await;
                 match result {
-                    Ok((sender, socket)) => {
+                    Ok((handle, socket)) => {
                         responder.into_inner()
-                            .send(Ok(sender.into()))
+                            .send(Ok(handle))
                             .ok();
-                        run_socket(socket).await;
+                        spawn(run_socket(socket).instrument(connection_span()));
                     },
                     Err(err) => {
+                        debug!("connect failed: {:#}", err);
                         responder.into_inner()
                             .send(Err(err))
                             .ok();
-                        break
                     }
                 }
-            },
-            Some(NewSocketEvent::Close(application_error, reason)) => {
-                close_new(socket, application_error, reason).await.ok();
-                break
-            },
-            None => break,
-        };
-    }
-}
-
-async fn run_socket(socket: SocketRuntimeLocal) {
-    let peer = peer_socket_event(socket.runtime.clone());
-    let local = local_socket_event(socket);
-    tokio::pin!(local, peer);
-    
-    loop {
-        tokio::select! {
-            Some(()) = &mut local => {},
-            Ok(()) = &mut peer => {},
-            else => break,
-        }
-    }
-}
-
-async fn local_socket_event(mut socket: SocketRuntimeLocal) -> Option<()> {
-    match socket.receiver.recv().await.unwrap() {
-        SocketEvent::Close(application_error, reason) => {
-            close(&socket, application_error, reason).await.ok();
+                drop(slot);
+            }.instrument(Span::current()));
+            Some(())
+        },
+        Some(NewSocketEvent::Rebind(responder, sock_addr)) => {
+            let result = rebind(&socket.runtime, sock_addr).await;
+            responder.into_inner()
+                .send(result)
+                .ok();
+            Some(())
+        },
+        Some(NewSocketEvent::LocalAddress(responder)) => {
+            let result = local_address(&socket.runtime.state).await;
+            responder.into_inner()
+                .send(result)
+                .ok();
+            Some(())
+        },
+        Some(NewSocketEvent::Ready) => {
+            socket.ready().await;
+            Some(())
+        },
+        Some(NewSocketEvent::Close(application_error, reason)) => {
+            log_failure("close", close_new(socket, application_error, reason).await);
+            info!("closed");
             None
         },
-        SocketEvent::OpenBiStream(responder) => {
-            let stream = open_bi_stream(&mut socket).await;
+        Some(NewSocketEvent::CloseEndpoint(application_error, reason)) => {
+            log_failure("close endpoint", close_endpoint(socket, application_error, reason).await);
+            Some(())
+        },
+        Some(NewSocketEvent::WaitIdle(responder, timeout)) => {
+            let runtime = socket.runtime.clone();
+            let timeout = runtime.timeout(timeout).await;
+            tokio::spawn(async move {
+                let result = within(timeout, "Wait Idle Timeout.", wait_idle(&runtime)).await;
+                responder.into_inner()
+                    .send(result)
+                    .ok();
+                drop(slot);
+            }.instrument(Span::current()));
+            Some(())
+        },
+        Some(NewSocketEvent::Shutdown(responder, timeout, close)) => {
+            let timeout = socket.runtime.timeout(timeout).await;
+            let result = shutdown(socket, timeout, close).await;
+            match result.as_ref() {
+                Ok(drained) => info!(drained, "shut down"),
+                Err(error) => warn!("shutdown failed: {:#}", error),
+            }
             responder.into_inner()
-                .send(stream)
-                .ok()
+                .send(result)
+                .ok();
+            None
         },
-        SocketEvent::OpenUniStream(responder) => {
-            let stream = open_uni_stream(&mut socket).await;
+        Some(NewSocketEvent::ControllingProcess(responder, pid)) => {
+            let result = new_socket_controlling_process(socket, pid).await;
             responder.into_inner()
-                .send(stream)
+                .send(result)
                 .ok()
         },
+        Some(NewSocketEvent::Dropped) | Some(NewSocketEvent::OwnerDown) | None => {
+            log_failure("drop", drop_new(socket).await);
+            info!("dropped");
+            None
+        },
     }
 }
 
-async fn peer_socket_event(socket: SocketRuntime) -> Result<()> {
-    let unis = socket
-        .state
-        .peer
-        .uni_streams
-        .clone();
-    let bis = socket
-        .state
-        .peer
-        .bi_streams
-        .clone();
-    let uni_streams = peer_uni_stream(unis);
-    let bi_streams = peer_bi_stream(bis);
+// Waits for the next client to arrive, or forever once the listener has stopped accepting.
+async fn next_incoming(incoming: &mut Option<Incoming>) -> Option<Connecting> {
+    match incoming {
+        Some(incoming) => incoming.next().await,
+        None => future::pending().await,
+    }
+}
 
-    tokio::pin!(uni_streams, bi_streams);
-    
-    let stream = tokio::select! {
-        Some(state) = &mut uni_streams => state.map(|state| StreamRuntime::new(state, socket.configs.clone())),
-        Some(state) = &mut bi_streams => state.map(|state| StreamRuntime::new(state, socket.configs.clone())),
-        else => Err(anyhow::anyhow!("Incoming Streams Closed by peer.")),
-    };
-    announce_stream(stream?).await
+// Waits for the operation of a call waiting in line to be cancelled or dropped. Returns the
+// call's place in line and whether it was cancelled.
+async fn next_cancelled<T>(waiting: &mut VecDeque<T>, cancel: fn(&mut T) -> Option<&mut Cancel>) -> (usize, bool) {
+    let mut cancels : FuturesUnordered<_> = waiting
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(index, call)| {
+            cancel(call).map(|cancel| async move { (index, cancel.await.is_ok()) })
+        })
+        .collect();
+    match cancels.next().await {
+        Some(cancelled) => cancelled,
+        None => future::pending().await,
+    }
 }
 
-async fn peer_uni_stream(uni_streams: Option<Arc<Mutex<IncomingUniStreams>>>) -> Option<Result<StreamState>> {
-    let mut lock = uni_streams?
-        .lock_owned()
-        .await;
-    let stream = (*lock)
-        .next()
+async fn run_socket(mut socket: SocketRuntimeLocal) {
+    if let Err(error) = socket.monitor_owner().await {
+        warn!("could not monitor owner: {:#}", error);
+        let close_code = socket.runtime.configs.socket_config.read().await.options.close_code();
+        log_failure("drop", drop_socket(&socket, close_code).await);
+        return
+    }
+    metrics::connection_opened();
+    let started = Instant::now();
+    notify_connected(&socket).await.ok();
+    check_peer_address(&mut socket).await.ok();
+    info!(peer = ?socket.peer_address, "connected");
+    let measurements = vec![(crate::interface::handshake_duration(), micros(socket.handshake))];
+    connection_telemetry(&socket, crate::interface::start(), measurements, None).await.ok();
+    let mut peer_address_check = time::interval(PEER_ADDRESS_INTERVAL);
+    let mut drain_check = time::interval(DRAIN_INTERVAL);
+    let reason = loop {
+        let peer = peer_socket_event(socket.runtime.clone());
+        let deadline = socket.peer_streams.next_deadline();
+        let timeout = time::delay_until(deadline.unwrap_or_else(Instant::now));
+        tokio::select! {
+            (event, slot) = next_event(&mut socket.receiver, &mut socket.control) => {
+                if local_socket_event(&mut socket, event, slot).await.is_none() {
+                    break anyhow::anyhow!("Closed locally.")
+                }
+            },
+            result = peer => {
+                match result {
+                    Ok((stream_type, state)) => {
+                        // The stream only counts against the connection once it's handed out.
+                        let stream = StreamRuntime::new(state, socket.runtime.configs.clone(), ConnectionOwner::Peer, socket.handle.clone(), socket.streams.clone());
+                        log_failure("incoming stream", incoming_stream(&mut socket, stream_type, stream).await);
+                    },
+                    Err(error) => break error,
+                }
+            },
+            _ = peer_address_check.tick() => {
+                check_peer_address(&mut socket).await.ok();
+            },
+            _ = next_tick(&mut socket.keep_alive) => {
+                log_failure("keep-alive", ping(&socket).await);
+            },
+            _ = drain_check.tick(), if socket.going_away => {
+                // Only the connection itself holds the count once its streams are done.
+                if Arc::strong_count(&socket.streams) == 1 {
+                    log_failure("close", close_drained(&mut socket).await);
+                    break anyhow::anyhow!("Closed locally.")
+                }
+            },
+            _ = timeout, if deadline.is_some() => {
+                for waiter in socket.peer_streams.expired(Instant::now()) {
+                    waiter.responder.into_inner()
+                        .send(Err(anyhow::anyhow!("Accept Stream Timeout.")))
+                        .ok();
+                }
+            },
+        }
+    };
+    metrics::connection_closed();
+    let measurements = vec![(crate::interface::duration(), micros(started.elapsed()))];
+    connection_telemetry(&socket, crate::interface::stop(), measurements, Some(reason.to_string())).await.ok();
+    if idle_timed_out(&reason) {
+        info!("idle timeout");
+        notify_idle_timeout(&socket).await.ok();
+    } else {
+        info!(%reason, "closed");
+        notify_closed(&socket, reason.to_string()).await.ok();
+    }
+}
+
+fn idle_timed_out(reason: &anyhow::Error) -> bool {
+    match reason.downcast_ref::<ConnectionError>() {
+        Some(ConnectionError::TimedOut) => true,
+        _ => false,
+    }
+}
+
+// Tells the owner the connection is established.
+async fn notify_connected(socket: &SocketRuntimeLocal) -> Result<()> {
+    let owner = socket.runtime.configs.socket_config.read().await.owner();
+    if let Some(pid) = owner {
+        let msg = (crate::interface::quic_connected(), socket.socket()?.id());
+        send_to(&pid, msg)?;
+    }
+    Ok(())
+}
+
+// Tells the owner the connection has ended. Nothing is sent once the socket has been dropped.
+async fn notify_closed(socket: &SocketRuntimeLocal, reason: String) -> Result<()> {
+    let owner = socket.runtime.configs.socket_config.read().await.owner();
+    if let Some(pid) = owner {
+        let msg = (crate::interface::quic_closed(),
+                   socket.socket()?.id(),
+                   reason);
+        send_to(&pid, msg)?;
+    }
+    Ok(())
+}
+
+// Tells the owner the connection is going away, so it can finish its streams.
+async fn notify_goaway(socket: &SocketRuntimeLocal) -> Result<()> {
+    let owner = socket.runtime.configs.socket_config.read().await.owner();
+    if let Some(pid) = owner {
+        let msg = (crate::interface::quic_goaway(), socket.socket()?.id());
+        send_to(&pid, msg)?;
+    }
+    Ok(())
+}
+
+// Tells the owner the connection was closed for being idle.
+async fn notify_idle_timeout(socket: &SocketRuntimeLocal) -> Result<()> {
+    let owner = socket.runtime.configs.socket_config.read().await.owner();
+    if let Some(pid) = owner {
+        let msg = (crate::interface::quic_idle_timeout(), socket.socket()?.id());
+        send_to(&pid, msg)?;
+    }
+    Ok(())
+}
+
+// Sends {:centaurus_telemetry, [:centaurus | event], measurements, metadata} to the socket's
+// telemetry_pid. Measurements are durations in microseconds.
+async fn send_telemetry<M>(configs: &Configs, event: [Atom; 2], measurements: Vec<(Atom, u64)>, metadata: M) -> Result<()>
+where M: Encoder {
+    let name = vec![crate::interface::centaurus(), event[0], event[1]];
+    let msg = (crate::interface::centaurus_telemetry(), name, measurements, metadata);
+    configs.socket_config.read().await.send_telemetry(msg)
+}
+
+// Reports a connection starting or stopping. The reason is only given when it stops.
+async fn connection_telemetry(socket: &SocketRuntimeLocal, event: Atom, measurements: Vec<(Atom, u64)>, reason: Option<String>) -> Result<()> {
+    let metadata = ConnectionMetadata {
+        socket: socket.socket().ok().map(|quic_socket| quic_socket.id()),
+        peer: socket.peer_address.map(crate::interface::types::SocketAddr),
+        reason,
+    };
+    send_telemetry(&socket.runtime.configs, [crate::interface::connection(), event], measurements, metadata).await
+}
+
+// Reports a stream opening or closing, with the stream's info as the metadata.
+async fn stream_telemetry(stream: &StreamRuntimeLocal, event: Atom, measurements: Vec<(Atom, u64)>) -> Result<()> {
+    let info = stream.runtime.info()?;
+    let metadata = StreamMetadata {
+        id: info.id,
+        direction: info.direction,
+        initiator: info.initiator,
+        socket: stream.runtime.socket.upgrade().map(|handle| handle.id()),
+    };
+    send_telemetry(&stream.runtime.configs, [crate::interface::stream(), event], measurements, metadata).await
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros() as u64
+}
+
+// Tells the listener's owner a peer's handshake failed.
+async fn notify_handshake_failed(socket: &SocketRuntime, peer: SocketAddr, reason: String) -> Result<()> {
+    let owner = socket.configs.socket_config.read().await.owner();
+    if let Some(pid) = owner {
+        let msg = (crate::interface::quic_handshake_failed(),
+                   crate::interface::types::SocketAddr(peer),
+                   reason);
+        send_to(&pid, msg)?;
+    }
+    Ok(())
+}
+
+// Handles an event from the socket's queue. The slot is its place in the queue, given back once
+// the event has been handled.
+async fn local_socket_event(socket: &mut SocketRuntimeLocal, event: Option<SocketEvent>, slot: Option<Slot<SocketEvent>>) -> Option<()> {
+    match event {
+        Some(SocketEvent::AcceptStream(responder, pid, stream_type, timeout)) => {
+            let timeout = socket.runtime.timeout(timeout).await;
+            let waiter = StreamWaiter {
+                responder,
+                owner: QuicStream::Pid(pid),
+                deadline: timeout.map(|timeout| Instant::now() + timeout),
+                _slot: slot,
+            };
+            socket.peer_streams.queues(stream_type).1.push_back(waiter);
+            serve_waiting_streams(socket, stream_type).await;
+            Some(())
+        },
+        Some(SocketEvent::Close(application_error, reason)) => {
+            log_failure("close", close(socket, application_error, reason).await);
+            None
+        },
+        Some(SocketEvent::GoAway(close)) => {
+            socket.going_away = true;
+            socket.drain_close = close;
+            log_failure("go away", notify_goaway(socket).await);
+            Some(())
+        },
+        Some(SocketEvent::OpenBiStream(responder, pid)) => {
+            let stream = open_bi_stream(socket, pid).await;
+            responder.into_inner()
+                .send(stream)
+                .ok()
+        },
+        Some(SocketEvent::OpenUniStream(responder, pid)) => {
+            let stream = open_uni_stream(socket, pid).await;
+            responder.into_inner()
+                .send(stream)
+                .ok()
+        },
+        Some(SocketEvent::ControllingProcess(responder, pid)) => {
+            let result = socket_controlling_process(socket, pid).await;
+            responder.into_inner()
+                .send(result)
+                .ok()
+        },
+        Some(SocketEvent::LocalAddress(responder)) => {
+            let result = local_address(&socket.runtime.state).await;
+            responder.into_inner()
+                .send(result)
+                .ok()
+        },
+        Some(SocketEvent::Rebind(responder, sock_addr)) => {
+            let result = rebind(&socket.runtime, sock_addr).await;
+            responder.into_inner()
+                .send(result)
+                .ok()
+        },
+        Some(SocketEvent::SetKeepAlive(responder, interval)) => {
+            let result = set_keep_alive(socket, interval).await;
+            responder.into_inner()
+                .send(result)
+                .ok()
+        },
+        Some(SocketEvent::Ready) => {
+            socket.ready().await;
+            Some(())
+        },
+        Some(SocketEvent::Dropped) | Some(SocketEvent::OwnerDown) | None => {
+            let close_code = socket.runtime.configs.socket_config.read().await.options.close_code();
+            log_failure("drop", drop_socket(socket, close_code).await);
+            None
+        },
+    }
+}
+
+async fn peer_socket_event(socket: SocketRuntime) -> Result<(StreamType, StreamState)> {
+    let unis = socket
+        .state
+        .peer
+        .uni_streams
+        .clone();
+    let bis = socket
+        .state
+        .peer
+        .bi_streams
+        .clone();
+    let uni_streams = peer_uni_stream(unis);
+    let bi_streams = peer_bi_stream(bis);
+
+    tokio::pin!(uni_streams, bi_streams);
+    
+    let (stream_type, state) = tokio::select! {
+        Some(state) = &mut uni_streams => (StreamType::Uni, state),
+        Some(state) = &mut bi_streams => (StreamType::Bi, state),
+        else => return Err(anyhow::anyhow!("Incoming Streams Closed by peer.")),
+    };
+    Ok((stream_type, state?))
+}
+
+async fn peer_uni_stream(uni_streams: Option<Arc<Mutex<IncomingUniStreams>>>) -> Option<Result<StreamState>> {
+    let mut lock = uni_streams?
+        .lock_owned()
+        .await;
+    let stream = (*lock)
+        .next()
         .await
         .map(|stream| stream.map(|s| s.into())
              .map_err(|e| e.into()));
@@ -388,75 +1322,243 @@ async fn peer_bi_stream(bi_streams: Option<Arc<Mutex<IncomingBiStreams>>>) -> Op
     stream
 }
 
-async fn accept(socket: &NewSocketRuntimeLocal) -> Result<Socket> {
-    let new_socket_state : SocketState = {
-        (*socket
-         .runtime
-         .state
-         .local
-         .as_ref()
-         .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
-         .lock()
-         .await)
-            .incoming
-            .as_mut()
-            .ok_or_else(|| anyhow::anyhow!("Incoming Streams closed."))?
-            .next()
-            .await
-            .ok_or_else(|| anyhow::anyhow!("Incoming Streams closed."))?
-            .await
-            .map(|s| s.into())?
-    };
-    
-    let (sender, socket_local) = {
+// Checks a client that has just arrived against the listener's admission policy, then either
+// refuses it or starts its handshake.
+fn admit(socket: &mut NewSocketRuntimeLocal, connecting: Connecting) {
+    let peer = connecting.remote_address();
+    let open = socket.open_connections();
+    let decision = socket.admission.admit(peer.ip(), open, Instant::now());
+    socket.counters.record(decision);
+    if let Err(refusal) = decision {
+        debug!(%peer, ?refusal, "connection refused");
+        refuse(connecting);
+        return
+    }
+    let started = Instant::now();
+    socket.handshakes.push(Box::pin(async move {
+        let result = connecting.await;
+        Handshake {
+            peer,
+            duration: started.elapsed(),
+            result,
+        }
+    }));
+}
+
+// Queues an established connection for the next accept. A failed handshake is reported to the
+// owner instead.
+async fn handshake_finished(socket: &mut NewSocketRuntimeLocal, handshake: Handshake) {
+    match handshake.result {
+        Ok(connection) => {
+            socket.established.push_back(Established {
+                connection,
+                handshake: handshake.duration,
+            });
+        },
+        Err(error) => {
+            metrics::handshake_failed();
+            warn!(peer = %handshake.peer, %error, "handshake failed");
+            notify_handshake_failed(&socket.runtime, handshake.peer, error.to_string()).await.ok();
+        },
+    }
+}
+
+// Hands established connections to the accepts waiting for them. Once the listener has stopped
+// accepting and has no connections left to hand out, the waiting accepts fail.
+async fn serve_accepts(socket: &mut NewSocketRuntimeLocal) {
+    while !socket.waiting.is_empty() && !socket.established.is_empty() {
+        let (waiter, established) = (socket.waiting.pop_front().unwrap(), socket.established.pop_front().unwrap());
+        let result = start_accepted(socket, established, waiter.owner).await;
+        waiter.responder.into_inner()
+            .send(result)
+            .ok();
+    }
+    if socket.incoming.is_none() && socket.handshakes.is_empty() && socket.established.is_empty() {
+        for waiter in socket.waiting.drain(..) {
+            waiter.responder.into_inner()
+                .send(Err(anyhow::anyhow!("Incoming Streams closed.")))
+                .ok();
+        }
+    }
+}
+
+// Starts the task of an accepted connection. The connection belongs to the given process.
+async fn start_accepted(socket: &mut NewSocketRuntimeLocal, established: Established, owner: LocalPid) -> Result<Socket> {
+    let endpoint = (*socket
+     .runtime
+     .state
+     .local
+     .as_ref()
+     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+     .lock()
+     .await)
+        .endpoint
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("Endpoint Inaccessible."))?;
+    let (new_socket, mut socket_local) = {
         let new_socket = SocketRuntime {
-            configs: socket.runtime.configs.clone(),
-            state: new_socket_state.clone(),
+            configs: socket.runtime.configs.fork_socket_for(owner).await,
+            state: (endpoint, established.connection).into(),
         };
-        SocketRuntimeLocal::new(new_socket)
+        SocketRuntimeLocal::new(new_socket).await
     };
+    socket_local.handshake = established.handshake;
     
-    tokio::spawn(async move {
-        run_socket(socket_local).await;
+    socket.accepted.retain(Accepted::open);
+    socket.accepted.push(Accepted {
+        socket: socket_local.handle.clone(),
+        running: Arc::downgrade(&socket_local.running),
     });
-    Ok(sender.into())
-}
-
-async fn connect(socket: &mut NewSocketRuntimeLocal, sock_addr: SocketAddr) -> Result<(AsyncSender<SocketEvent>, SocketRuntimeLocal)> {
-    let server_name = {
-        (*socket
-         .runtime
-         .configs
-         .socket_config
-         .read()
-         .await)
-            .server_name()?
+    spawn(run_socket(socket_local).instrument(connection_span()));
+    Ok(new_socket)
+}
+
+// quinn 0.6 only lets a server refuse with CONNECTION_REFUSED through its own concurrent
+// connection limit, which can't be counted or changed at runtime. A refused connection is closed
+// instead as soon as the server is able to, with the application error code 2 standing in for
+// CONNECTION_REFUSED (0x2), before it's handed to the owner.
+fn refuse(connecting: Connecting) {
+    if let Ok((new_connection, _accepted)) = connecting.into_0rtt() {
+        new_connection.connection.close(VarInt::from_u32(2), b"Connection Refused.");
+    }
+}
+
+async fn connect(socket: &SocketRuntime, peer_addr: PeerAddr) -> Result<(Socket, SocketRuntimeLocal)> {
+    let (server_name, ipv6_only) = {
+        let socket_config = socket.configs.socket_config.read().await;
+        (socket_config.server_name()?, socket_config.options.ipv6_only.unwrap_or(false))
     };
-    let new_socket_state : SocketState = {
-        (*socket
-         .runtime
-         .state
-         .local
-         .as_ref()
-         .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
-         .lock()
-         .await)
-            .endpoint
-            .as_mut()
-            .unwrap()
-            .connect(&sock_addr, &server_name)?
-            .await?
-            .into()
+    // Hostnames double as the server name when none is configured.
+    let (server_name, addrs) = match peer_addr {
+        PeerAddr::Addr(sock_addr) => (server_name, vec![sock_addr]),
+        PeerAddr::Host(host, port) => {
+            let addrs = net::lookup_host((host.as_str(), port))
+                .await
+                .context("Error resolving host.")?
+                .collect();
+            if server_name.is_empty() {
+                (host, addrs)
+            } else {
+                (server_name, addrs)
+            }
+        },
     };
+    // The endpoint is cloned so other calls on the socket don't wait on the handshake.
+    let endpoint = (*socket
+     .state
+     .local
+     .as_ref()
+     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+     .lock()
+     .await)
+        .endpoint
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("Endpoint Inaccessible."))?;
+    let local_addr = endpoint.local_addr().context("Error getting local address.")?;
+    let addrs = reachable(local_addr, ipv6_only, interleave(addrs))?;
+    let started = Instant::now();
+    let connection = connect_any(&endpoint, addrs, &server_name).await?;
+    let (new_socket_state, handshake) : (SocketState, Duration) = ((endpoint, connection).into(), started.elapsed());
     let socket = SocketRuntime {
-        configs: socket.runtime.configs.clone(),
+        configs: socket.configs.fork_socket().await,
         state: new_socket_state,
     };
-    Ok(SocketRuntimeLocal::new(socket))
+    let (handle, mut socket_local) = SocketRuntimeLocal::new(socket).await;
+    socket_local.handshake = handshake;
+    Ok((handle, socket_local))
 }
 
-// TODO: Find a way to combine these two close functions.
-async fn close_new(socket: NewSocketRuntimeLocal, application_error: ApplicationError, reason: Option<String>) -> Result<()> {
+// Races connections to each address in order, starting the next attempt when the last one fails or
+// hasn't connected within CONNECT_ATTEMPT_DELAY. The first to connect wins (RFC 8305).
+async fn connect_any(endpoint: &Endpoint, addrs: Vec<SocketAddr>, server_name: &str) -> Result<NewConnection> {
+    let mut pending = addrs.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut error = anyhow::anyhow!("Host has no addresses.");
+    loop {
+        if let Some(sock_addr) = pending.next() {
+            match endpoint.connect(&sock_addr, server_name) {
+                Ok(connecting) => attempts.push(connecting),
+                Err(err) => error = err.into(),
+            }
+        }
+        if attempts.is_empty() {
+            if pending.len() == 0 {
+                return Err(error)
+            }
+            continue
+        }
+        let more = pending.len() > 0;
+        tokio::select! {
+            result = attempts.next() => {
+                match result {
+                    Some(Ok(connection)) => return Ok(connection),
+                    Some(Err(err)) => {
+                        metrics::handshake_failed();
+                        debug!("connect attempt failed: {}", err);
+                        error = err.into()
+                    },
+                    None => (),
+                }
+            },
+            _ = time::delay_for(CONNECT_ATTEMPT_DELAY), if more => (),
+        }
+    }
+}
+
+// Keeps the addresses the endpoint can send to. An IPv4 endpoint only reaches IPv4 addresses,
+// and an IPv6 one reaches IPv4 addresses as mapped addresses when it's bound to the unspecified
+// address without IPV6_V6ONLY.
+fn reachable(local_addr: SocketAddr, ipv6_only: bool, addrs: Vec<SocketAddr>) -> Result<Vec<SocketAddr>> {
+    let dual_stack = match local_addr.ip() {
+        IpAddr::V6(ip) => ip.is_unspecified() && !ipv6_only,
+        IpAddr::V4(_) => false,
+    };
+    let addrs : Vec<SocketAddr> = addrs
+        .into_iter()
+        .filter_map(|sock_addr| match (local_addr.ip(), sock_addr.ip()) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => Some(sock_addr),
+            (IpAddr::V6(_), IpAddr::V4(ip)) if dual_stack => {
+                Some(SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), sock_addr.port()))
+            },
+            _ => None,
+        })
+        .collect();
+    if addrs.is_empty() {
+        return Err(anyhow::anyhow!("Host has no addresses reachable from the socket."))
+    }
+    Ok(addrs)
+}
+
+// Alternates between IPv6 and IPv4 addresses, starting with IPv6.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4) : (Vec<SocketAddr>, Vec<SocketAddr>) = addrs
+        .into_iter()
+        .partition(|sock_addr| sock_addr.is_ipv6());
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    let mut ordered = Vec::new();
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => break,
+            (first, second) => {
+                ordered.extend(first);
+                ordered.extend(second);
+            },
+        }
+    }
+    ordered
+}
+
+// Stops accepting and closes the endpoint, along with every connection on it.
+async fn close_new(socket: &mut NewSocketRuntimeLocal, application_error: ApplicationError, reason: Option<String>) -> Result<()> {
+    let reason = reason.unwrap_or_else(|| "".to_string());
+    let endpoint = stop_accepting(socket).await?;
+    endpoint.close(application_error.into(), reason.as_bytes());
+    Ok(())
+}
+
+// Closes every connection on the endpoint, but keeps the endpoint so wait_idle can see the
+// closes through.
+async fn close_endpoint(socket: &NewSocketRuntimeLocal, application_error: ApplicationError, reason: Option<String>) -> Result<()> {
     let reason = reason.unwrap_or_else(|| "".to_string());
     (*socket
      .runtime
@@ -466,11 +1568,146 @@ async fn close_new(socket: NewSocketRuntimeLocal, application_error: Application
      .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
      .lock()
      .await)
-        .connection
-        .as_mut()
-        .unwrap()
+        .endpoint
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Endpoint Inaccessible."))?
         .close(application_error.into(), reason.as_bytes());
-            
+    Ok(())
+}
+
+// Waits until every connection on the endpoint has finished closing.
+async fn wait_idle(socket: &SocketRuntime) -> Result<()> {
+    let endpoint = (*socket
+     .state
+     .local
+     .as_ref()
+     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+     .lock()
+     .await)
+        .endpoint
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("Endpoint Inaccessible."))?;
+    endpoint.wait_idle().await;
+    Ok(())
+}
+
+// Stops accepting and tells each accepted connection it is going away. The connections close,
+// with the given error if there is one, once their streams are done. Waits up to the timeout
+// for them before closing the endpoint. Returns how many connections ended in time.
+async fn shutdown(socket: &mut NewSocketRuntimeLocal, timeout: Option<Duration>, close: Option<(ApplicationError, Option<String>)>) -> Result<usize> {
+    let endpoint = stop_accepting(socket).await?;
+    socket.accepted.retain(Accepted::open);
+    let open = socket.accepted.len();
+    for accepted in &socket.accepted {
+        // A connection whose handle is gone is already closing.
+        if let Some(handle) = accepted.socket.upgrade() {
+            log_failure("go away", handle.control(SocketEvent::GoAway(close.clone())));
+        }
+    }
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut drain_check = time::interval(DRAIN_INTERVAL);
+    while !socket.accepted.is_empty() && deadline.map_or(true, |deadline| Instant::now() < deadline) {
+        drain_check.tick().await;
+        socket.accepted.retain(Accepted::open);
+    }
+    let (close_code, reason) : (VarInt, String) = match close {
+        Some((application_error, reason)) => (application_error.into(), reason.unwrap_or_default()),
+        None => (socket.runtime.configs.socket_config.read().await.options.close_code(), String::new()),
+    };
+    endpoint.close(close_code, reason.as_bytes());
+    Ok(open - socket.accepted.len())
+}
+
+// Takes the listener's incoming connections and endpoint. New connections are refused from here on.
+async fn stop_accepting(socket: &mut NewSocketRuntimeLocal) -> Result<Endpoint> {
+    socket.incoming.take();
+    let mut local = socket
+        .runtime
+        .state
+        .local
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+        .lock()
+        .await;
+    local.incoming.take();
+    local.endpoint
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("Endpoint Inaccessible."))
+}
+
+// Moves the endpoint to a new local address. Connections on the endpoint migrate with it.
+async fn rebind(socket: &SocketRuntime, sock_addr: SocketAddr) -> Result<()> {
+    let udp_socket = socket.configs.socket_config.read().await.bind(sock_addr)?;
+    (*socket
+     .state
+     .local
+     .as_ref()
+     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+     .lock()
+     .await)
+        .endpoint
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Endpoint Inaccessible."))?
+        .rebind(udp_socket)
+        .context("Error rebinding socket.")
+}
+
+// The address the endpoint is bound to, with the port filled in if it was picked by the system.
+async fn local_address(state: &SocketState) -> Result<SocketAddr> {
+    (*state
+     .local
+     .as_ref()
+     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+     .lock()
+     .await)
+        .endpoint
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Endpoint Inaccessible."))?
+        .local_addr()
+        .context("Error reading local address.")
+}
+
+// Tells the owner when the peer has moved to a new address.
+async fn check_peer_address(socket: &mut SocketRuntimeLocal) -> Result<()> {
+    let address = (*socket
+     .runtime
+     .state
+     .local
+     .as_ref()
+     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+     .lock()
+     .await)
+        .connection
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Connection Inaccessible."))?
+        .remote_address();
+    let previous = socket.peer_address.replace(address);
+    if previous.map_or(false, |previous| previous != address) {
+        let owner = socket.runtime.configs.socket_config.read().await.owner();
+        if let Some(pid) = owner {
+            let msg = (crate::interface::quic_peer_address(),
+                       socket.socket()?.id(),
+                       crate::interface::types::SocketAddr(address));
+            send_to(&pid, msg)?;
+        }
+    }
+    Ok(())
+}
+
+// Stops accepting new connections and lets go of the endpoint. Connections that were already
+// accepted keep the endpoint running until they are closed.
+async fn drop_new(socket: &mut NewSocketRuntimeLocal) -> Result<()> {
+    socket.incoming.take();
+    let mut local = socket
+        .runtime
+        .state
+        .local
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+        .lock()
+        .await;
+    local.incoming.take();
+    local.endpoint.take();
     Ok(())
 }
 
@@ -492,8 +1729,103 @@ async fn close(socket: &SocketRuntimeLocal, application_error: ApplicationError,
     Ok(())
 }
 
-async fn open_bi_stream(socket: &mut SocketRuntimeLocal) -> Result<Stream> {
-    let state : StreamState = (*socket
+// Hands the listener over to a new owner and monitors it in place of the old one.
+async fn new_socket_controlling_process(socket: &mut NewSocketRuntimeLocal, pid: LocalPid) -> Result<()> {
+    {
+        let mut config = socket.runtime.configs.socket_config.write().await;
+        *config = config.with_owner(pid);
+    }
+    socket.monitor_owner().await
+}
+
+// Hands the socket over to a new owner and monitors it in place of the old one.
+async fn socket_controlling_process(socket: &mut SocketRuntimeLocal, pid: LocalPid) -> Result<()> {
+    {
+        let mut config = socket.runtime.configs.socket_config.write().await;
+        *config = config.with_owner(pid);
+    }
+    socket.monitor_owner().await
+}
+
+// Starts or stops the connection's pings. The first is sent right away, so a peer that can't
+// take them is reported to the caller.
+async fn set_keep_alive(socket: &mut SocketRuntimeLocal, interval: u64) -> Result<()> {
+    socket.keep_alive = None;
+    if interval == 0 {
+        return Ok(())
+    }
+    ping(socket).await?;
+    let interval = Duration::from_millis(interval);
+    socket.keep_alive = Some(time::interval_at(Instant::now() + interval, interval));
+    Ok(())
+}
+
+// Sends the peer an empty datagram, which the peer acknowledges like any other packet.
+async fn ping(socket: &SocketRuntimeLocal) -> Result<()> {
+    (*socket
+     .runtime
+     .state
+     .local
+     .as_ref()
+     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+     .lock()
+     .await)
+        .connection
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Connection Inaccessible."))?
+        .send_datagram(Bytes::new())
+        .context("Error sending keep-alive.")
+}
+
+// Waits for the interval's next tick, or forever without one.
+async fn next_tick(interval: &mut Option<time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        },
+        None => future::pending().await,
+    }
+}
+
+// Closes a connection that went away once its streams are done.
+async fn close_drained(socket: &mut SocketRuntimeLocal) -> Result<()> {
+    match socket.drain_close.take() {
+        Some((application_error, reason)) => close(socket, application_error, reason).await,
+        None => {
+            let close_code = socket.runtime.configs.socket_config.read().await.options.close_code();
+            drop_socket(socket, close_code).await
+        },
+    }
+}
+
+async fn drop_socket(socket: &SocketRuntimeLocal, close_code: VarInt) -> Result<()> {
+    if let Some(connection) = (*socket
+     .runtime
+     .state
+     .local
+     .as_ref()
+     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
+     .lock()
+     .await)
+        .connection
+        .as_ref() {
+            connection.close(close_code, b"");
+        }
+    Ok(())
+}
+
+// The owner of a stream the host opens: the stream config's stream_pid, or the process that
+// opened it when there is none.
+async fn stream_owner(socket: &SocketRuntimeLocal, caller: LocalPid) -> LocalPid {
+    socket.runtime.configs.stream_config.read().await.owner().unwrap_or(caller)
+}
+
+// Opens a stream for the given process, unless the stream config names its owner.
+async fn open_bi_stream(socket: &mut SocketRuntimeLocal, owner: LocalPid) -> Result<Stream> {
+    if socket.going_away {
+        return Err(anyhow::anyhow!("Socket Going Away."))
+    }
+    let open = (*socket
      .runtime
      .state
      .local
@@ -504,20 +1836,28 @@ async fn open_bi_stream(socket: &mut SocketRuntimeLocal) -> Result<Stream> {
         .connection
         .as_mut()
         .unwrap()
-        .open_bi()
-        .into();
-    let runtime = StreamRuntime::new(state, socket.runtime.configs.clone());
-    let (sender, stream) = StreamRuntimeLocal::new(runtime);
+        .open_bi();
+    let (mut send, recv) = open.await?;
+    let configs = socket.runtime.configs.fork_stream_for(stream_owner(socket, owner).await).await;
+    if let Some(priority) = configs.stream_config.read().await.priority {
+        send.set_priority(priority).ok();
+    }
+    let state : StreamState = (send, recv).into();
+    let runtime = StreamRuntime::new(state, configs, ConnectionOwner::Host, socket.handle.clone(), socket.streams.clone());
+    let (quic_stream, stream) = StreamRuntimeLocal::new(runtime).await;
 
-    tokio::spawn(async move {
-        run_stream(stream).await;
-    });
+    let span = stream_span(&stream);
+    spawn(run_stream(stream).instrument(span));
     
-    Ok(sender.into())
+    Ok(quic_stream)
 }
 
-async fn open_uni_stream(socket: &mut SocketRuntimeLocal) -> Result<Stream> {
-    let state : StreamState = (*socket
+// Opens a stream for the given process, unless the stream config names its owner.
+async fn open_uni_stream(socket: &mut SocketRuntimeLocal, owner: LocalPid) -> Result<Stream> {
+    if socket.going_away {
+        return Err(anyhow::anyhow!("Socket Going Away."))
+    }
+    let open = (*socket
      .runtime
      .state
      .local
@@ -528,132 +1868,351 @@ async fn open_uni_stream(socket: &mut SocketRuntimeLocal) -> Result<Stream> {
         .connection
         .as_mut()
         .unwrap()
-        .open_uni()
-        .into();
-    let runtime = StreamRuntime::new(state, socket.runtime.configs.clone());
-    let (sender, stream) = StreamRuntimeLocal::new(runtime);
+        .open_uni();
+    let mut send = open.await?;
+    let configs = socket.runtime.configs.fork_stream_for(stream_owner(socket, owner).await).await;
+    if let Some(priority) = configs.stream_config.read().await.priority {
+        send.set_priority(priority).ok();
+    }
+    let state : StreamState = send.into();
+    let runtime = StreamRuntime::new(state, configs, ConnectionOwner::Host, socket.handle.clone(), socket.streams.clone());
+    let (quic_stream, stream) = StreamRuntimeLocal::new(runtime).await;
 
-    tokio::spawn(async move {
-        run_stream(stream).await;
-    });
+    let span = stream_span(&stream);
+    spawn(run_stream(stream).instrument(span));
     
-    Ok(sender.into())
+    Ok(quic_stream)
 }
 
-async fn announce_stream(stream: StreamRuntime) -> Result<()> {
-    let (sender, stream_local) = StreamRuntimeLocal::new(stream.clone());
-    tokio::spawn(async move {
-        run_stream(stream_local).await;
-    });
-    let config_lock = stream.configs.socket_config.read().await;
-    // I don't like this. Should try to find something better.
-    // Maybe involving a boxed fn for creating the stream.
-    (*config_lock).send(crate::interface::types::Stream::from(Stream(sender)))?;
-    Ok(())
+// Starts the task for a stream opened by the peer. The stream belongs to the given process.
+async fn start_peer_stream(mut stream: StreamRuntime, owner: LocalPid) -> Stream {
+    stream.configs = stream.configs.fork_stream_for(owner).await;
+    let (quic_stream, stream_local) = StreamRuntimeLocal::new(stream).await;
+    let span = stream_span(&stream_local);
+    spawn(run_stream(stream_local).instrument(span));
+    quic_stream
 }
 
-// TODO: Add active read capability. Similar to peer_socket_event above.
-async fn run_stream(stream: StreamRuntimeLocal) {
-    let local = local_stream_event(stream);
-    tokio::pin!(local);
-    
+// Sends a stream opened by the peer to the process picked by the socket's stream acceptor,
+// or queues it for accept_stream.
+async fn incoming_stream(socket: &mut SocketRuntimeLocal, stream_type: StreamType, stream: StreamRuntime) -> Result<()> {
+    let count = socket.peer_streams.next_count(stream_type);
+    let owner = socket.runtime.configs.socket_config.read().await.stream_owner(stream_type, count);
+    match owner {
+        Some(pid) => {
+            let quic_socket = socket.socket()?;
+            let id = stream.id()?;
+            let quic_stream = start_peer_stream(stream, pid.clone()).await;
+            let msg = (crate::interface::quic_stream(),
+                       quic_socket.id(),
+                       crate::interface::types::Stream::from(quic_stream),
+                       stream_type,
+                       id);
+            send_to(&pid, msg)
+        },
+        None => {
+            socket.peer_streams.queues(stream_type).0.push_back(stream);
+            serve_waiting_streams(socket, stream_type).await;
+            Ok(())
+        },
+    }
+}
+
+// Hands queued streams to the accept_stream calls waiting for them.
+async fn serve_waiting_streams(socket: &mut SocketRuntimeLocal, stream_type: StreamType) {
     loop {
-        tokio::select! {
-            Some(()) = &mut local => {},
-            else => break
+        let (streams, waiting) = socket.peer_streams.queues(stream_type);
+        if streams.is_empty() || waiting.is_empty() {
+            break
         }
+        let (stream, waiter) = (streams.pop_front().unwrap(), waiting.pop_front().unwrap());
+        let QuicStream::Pid(pid) = waiter.owner;
+        let quic_stream = start_peer_stream(stream, pid).await;
+        waiter.responder.into_inner()
+            .send(Ok(quic_stream))
+            .ok();
+    }
+}
+
+// TODO: Add active read capability. Similar to peer_socket_event above.
+async fn run_stream(mut stream: StreamRuntimeLocal) {
+    stream.start_halves();
+    if let Err(error) = stream.monitor_owner().await {
+        warn!("could not monitor owner: {:#}", error);
+        let close_code = stream.runtime.configs.stream_config.read().await.options.close_code();
+        drop_stream(&mut stream, close_code);
+        return
     }
+    debug!("opened");
+    let started = Instant::now();
+    stream_telemetry(&stream, crate::interface::open(), Vec::new()).await.ok();
+    while let Some(()) = local_stream_event(&mut stream).await {}
+    debug!("finished");
+    let measurements = vec![(crate::interface::duration(), micros(started.elapsed()))];
+    stream_telemetry(&stream, crate::interface::close(), measurements).await.ok();
 }
 
-async fn local_stream_event(mut stream: StreamRuntimeLocal) -> Option<()> {
-    match stream.receiver.recv().await.unwrap() {
-        StreamEvent::CloseStream(application_error) => {
-            close_stream(&stream, application_error).await.unwrap();
+async fn local_stream_event(stream: &mut StreamRuntimeLocal) -> Option<()> {
+    // Reads and writes take their place in the queue along to the half running them.
+    let (event, slot) = next_event(&mut stream.receiver, &mut stream.control).await;
+    match event {
+        Some(StreamEvent::CloseStream(application_error)) => {
+            close_stream(stream, application_error);
             None
         },
-        StreamEvent::Read(responder, buffer, Some(timeout)) => {
-            if let Some(result) = time::timeout(timeout, read(&mut stream, buffer))
-                .await
-                .unwrap_or_else(|error| Some(Err(anyhow::Error::new(error)))) {
-                    responder.into_inner()
-                        .send(result)
-                        .ok()
-                } else {
-                    responder.into_inner()
-                        .send(Err(anyhow::anyhow!("Stream Closed.")))
-                        .ok();
-                    None
-                }
+        Some(StreamEvent::Info(responder)) => {
+            responder.into_inner()
+                .send(stream.runtime.info())
+                .ok()
         },
-        StreamEvent::Read(responder, buffer, None) => {
-            if let Some(result) = read(&mut stream, buffer).await {
-                responder.into_inner()
-                    .send(result)
-                    .ok()
-            } else {
-                responder.into_inner()
-                    .send(Err(anyhow::anyhow!("Stream Closed.")))
-                    .ok();
-                None
-            }
+        Some(StreamEvent::SetPriority(responder, priority)) => {
+            queue_write(stream, WriteRequest::Priority(responder, priority, slot));
+            Some(())
+        },
+        Some(StreamEvent::Split(responder)) => {
+            let result = split_stream(stream).await;
+            let split = result.is_ok();
+            responder.into_inner()
+                .send(result)
+                .ok();
+            // The halves now run on their own tasks.
+            if split { None } else { Some(()) }
         },
-        StreamEvent::Write(responder, buffer) => {
-            let result = write(&mut stream, buffer).await;
+        Some(StreamEvent::ControllingProcess(responder, pid)) => {
+            let result = stream_controlling_process(stream, pid).await;
             responder.into_inner()
                 .send(result)
                 .ok()
         },
+        Some(StreamEvent::Dropped) | Some(StreamEvent::OwnerDown) | None => {
+            let close_code = stream.runtime.configs.stream_config.read().await.options.close_code();
+            drop_stream(stream, close_code);
+            None
+        },
+        Some(StreamEvent::Read(responder, buffer, timeout, cancel)) => {
+            let timeout = stream.runtime.timeout(timeout).await;
+            match stream.reads.as_mut() {
+                Some(reads) => {
+                    if let Err(TrySendError::Full((responder, ..))) = reads.requests.try_send((responder, buffer, timeout, cancel, slot)) {
+                        responder.into_inner()
+                            .send(Err(Busy.into()))
+                            .ok();
+                    }
+                },
+                None => {
+                    responder.into_inner()
+                        .send(Err(anyhow::anyhow!("Stream is send only.")))
+                        .ok();
+                },
+            }
+            Some(())
+        },
+        Some(StreamEvent::Ready) => {
+            stream.ready().await;
+            Some(())
+        },
+        Some(StreamEvent::Write(responder, buffer, timeout)) => {
+            let timeout = stream.runtime.timeout(timeout).await;
+            queue_write(stream, WriteRequest::Write(responder, buffer, timeout, slot));
+            Some(())
+        },
     }
 }
 
-async fn close_stream(stream: &StreamRuntimeLocal, application_error: ApplicationError) -> Result<()> {
-    (*stream
-     .runtime
-     .state
-     .local
-     .as_ref()
-     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
-     .lock()
-     .await)
-        .recv
-        .as_mut()
-        .unwrap()
-        .stop(application_error.into())
-        .map_err(|_err| anyhow::anyhow!("Error Closing Stream."))
+// Hands the request to the sending half's task, answering it straight away when it can't be.
+fn queue_write(stream: &mut StreamRuntimeLocal, request: WriteRequest) {
+    let refused = match stream.writes.as_mut() {
+        Some(writes) => match writes.requests.try_send(request) {
+            Err(TrySendError::Full(request)) => Some((request, Busy.into())),
+            _ => None,
+        },
+        None => Some((request, anyhow::anyhow!("Stream is receive only."))),
+    };
+    if let Some((request, error)) = refused {
+        request.into_responder()
+            .into_inner()
+            .send(Err(error))
+            .ok();
+    }
+}
+
+// Stops the receiving half and finishes the sending half. The halves of a split stream only
+// close their own side.
+fn close_stream(stream: &mut StreamRuntimeLocal, application_error: ApplicationError) {
+    stream.stop_halves(HalfShutdown::Close(application_error.into()));
+}
+
+// Moves the sending and receiving halves of a bi stream onto tasks of their own. Both halves
+// start out with the stream's owner.
+async fn split_stream(stream: &mut StreamRuntimeLocal) -> Result<(Stream, Stream)> {
+    let (send, recv) = match (stream.runtime.state.send.take(), stream.runtime.state.recv.take()) {
+        (Some(send), Some(recv)) => (send, recv),
+        (send, recv) => {
+            stream.runtime.state.send = send;
+            stream.runtime.state.recv = recv;
+            return Err(anyhow::anyhow!("Only bidirectional streams can be split."))
+        },
+    };
+    // Abandons any read or write still running, so the new halves never wait on it.
+    stream.reads = None;
+    stream.writes = None;
+    let mut send_half = stream.runtime.clone();
+    send_half.configs = send_half.configs.fork_stream().await;
+    send_half.state.send = Some(send);
+    let mut recv_half = stream.runtime.clone();
+    recv_half.configs = recv_half.configs.fork_stream().await;
+    recv_half.state.recv = Some(recv);
+    let (send_stream, send_local) = StreamRuntimeLocal::new(send_half).await;
+    let (recv_stream, recv_local) = StreamRuntimeLocal::new(recv_half).await;
+    let (send_span, recv_span) = (stream_span(&send_local), stream_span(&recv_local));
+    spawn(run_stream(send_local).instrument(send_span));
+    spawn(run_stream(recv_local).instrument(recv_span));
+    Ok((send_stream, recv_stream))
+}
+
+// Hands the stream over to a new owner and monitors it in place of the old one.
+async fn stream_controlling_process(stream: &mut StreamRuntimeLocal, pid: LocalPid) -> Result<()> {
+    {
+        let mut config = stream.runtime.configs.stream_config.write().await;
+        *config = config.with_owner(pid);
+    }
+    stream.monitor_owner().await
+}
+
+// Finishes the sending half and stops the receiving half of a stream whose handle was dropped.
+fn drop_stream(stream: &mut StreamRuntimeLocal, close_code: VarInt) {
+    stream.stop_halves(HalfShutdown::Drop(close_code));
+}
+
+async fn set_priority(send: &Mutex<SendStream>, priority: i32) -> Result<()> {
+    send.lock()
+        .await
+        .set_priority(priority)
+        .map_err(|_err| anyhow::anyhow!("Error Setting Stream Priority."))
+}
+
+// Reads run on their own task so a pending read never holds up writes on the same stream. The
+// task stops the receiving half itself when told to shut down.
+async fn run_reads(recv: Arc<Mutex<RecvStream>>, mut reads: QueueReceiver<ReadRequest>, mut shutdown: oneshot::Receiver<HalfShutdown>) {
+    // Reads taken off the queue while another is running, so a cancel is seen while they wait.
+    let mut waiting : VecDeque<ReadRequest> = VecDeque::new();
+    let shutdown = 'reads: loop {
+        let request = match waiting.pop_front() {
+            Some(request) => Some(request),
+            None => tokio::select! {
+                request = reads.recv() => request,
+                shutdown = &mut shutdown => break shutdown.ok(),
+            },
+        };
+        let (responder, buffer, timeout, cancel, _slot) = match request {
+            Some(request) => request,
+            // The queue is only dropped along with the signal.
+            None => break shutdown.try_recv().ok(),
+        };
+        let operation = cancellable(cancel, within(timeout, "Read Timeout.", read(&recv, buffer)));
+        tokio::pin!(operation);
+        let result = loop {
+            tokio::select! {
+                result = &mut operation => break result,
+                Some(request) = reads.recv() => waiting.push_back(request),
+                (index, cancelled) = next_cancelled(&mut waiting, |request| request.3.as_mut()) => {
+                    if !cancelled {
+                        // The operation was dropped without cancelling, so the read keeps waiting.
+                        waiting[index].3 = None;
+                    } else if let Some((responder, ..)) = waiting.remove(index) {
+                        responder.into_inner()
+                            .send(Err(Cancelled.into()))
+                            .ok();
+                    }
+                },
+                shutdown = &mut shutdown => {
+                    let responders = Some(responder).into_iter().chain(waiting.drain(..).map(|request| request.0));
+                    for responder in responders {
+                        responder.into_inner()
+                            .send(Err(anyhow::anyhow!("Stream Closed.")))
+                            .ok();
+                    }
+                    break 'reads shutdown.ok()
+                },
+            }
+        };
+        if let Ok(count) = result.as_ref() {
+            metrics::bytes_read(*count);
+        }
+        responder.into_inner()
+            .send(result)
+            .ok();
+    };
+    if let Some(shutdown) = shutdown {
+        let stopped = recv.lock()
+            .await
+            .stop(shutdown.code())
+            .map_err(|_err| anyhow::anyhow!("Error Closing Stream."));
+        log_failure("stop", stopped);
+    }
 }
 
-async fn read(stream: &mut StreamRuntimeLocal, buffer: Arc<Mutex<Vec<u8>>>) -> Option<Result<usize>> {
+// Writes run on their own task, which finishes the sending half itself when told to shut down.
+async fn run_writes(send: Arc<Mutex<SendStream>>, mut writes: QueueReceiver<WriteRequest>, mut shutdown: oneshot::Receiver<HalfShutdown>) {
+    let shutdown = loop {
+        let request = tokio::select! {
+            request = writes.recv() => request,
+            shutdown = &mut shutdown => break shutdown.ok(),
+        };
+        let (responder, operation, _slot) = match request {
+            Some(WriteRequest::Write(responder, buffer, timeout, slot)) => {
+                (responder, future::Either::Left(within(timeout, "Write Timeout.", write(&send, buffer))), slot)
+            },
+            Some(WriteRequest::Priority(responder, priority, slot)) => {
+                (responder, future::Either::Right(set_priority(&send, priority)), slot)
+            },
+            None => break shutdown.try_recv().ok(),
+        };
+        let result = tokio::select! {
+            result = operation => result,
+            shutdown = &mut shutdown => {
+                responder.into_inner()
+                    .send(Err(anyhow::anyhow!("Stream Closed.")))
+                    .ok();
+                break shutdown.ok()
+            },
+        };
+        responder.into_inner()
+            .send(result)
+            .ok();
+    };
+    if let Some(shutdown) = shutdown {
+        let mut send = send.lock().await;
+        let finished = send.finish()
+            .await
+            .map_err(|_err| anyhow::anyhow!("Error Closing Stream."));
+        match shutdown {
+            HalfShutdown::Close(_code) => log_failure("finish", finished),
+            HalfShutdown::Drop(code) => {
+                if finished.is_err() {
+                    send.reset(code).ok();
+                }
+            },
+        }
+    }
+}
+
+async fn read(recv: &Mutex<RecvStream>, buffer: Arc<Mutex<Vec<u8>>>) -> Result<usize> {
     let mut buf = buffer.lock().await;
-    (*stream
-     .runtime
-     .state
-     .local
-     .as_ref()?
-     .lock()
-     .await)
-        .recv
-        .as_mut()
-        .unwrap()
+    recv.lock()
+        .await
         .read(&mut (*buf.as_mut_slice()))
         .await
-        .map_err(|_err| anyhow::anyhow!("Error Reading from Stream."))
-        .transpose()
-        
+        .map_err(|_err| anyhow::anyhow!("Error Reading from Stream."))?
+        .ok_or_else(|| anyhow::anyhow!("Stream Closed."))
 }
 
-async fn write(stream: &mut StreamRuntimeLocal, mut buffer: Vec<u8>) -> Result<()> {
-    (*stream
-     .runtime
-     .state
-     .local
-     .as_ref()
-     .ok_or_else(|| anyhow::anyhow!("Local State Inaccessible."))?
-     .lock()
-     .await)
-        .send
-        .as_mut()
-        .unwrap()
+async fn write(send: &Mutex<SendStream>, mut buffer: Vec<u8>) -> Result<()> {
+    send.lock()
+        .await
         .write_all(buffer.as_mut_slice())
         .await
-        .context("Error Writing to Stream.")
+        .context("Error Writing to Stream.")?;
+    metrics::bytes_written(buffer.len());
+    Ok(())
 }
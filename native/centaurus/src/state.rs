@@ -8,10 +8,9 @@ use quinn::{
     IncomingBiStreams,
     IncomingUniStreams,
     NewConnection,
-    OpenBi,
-    OpenUni,
     RecvStream,
     SendStream,
+    StreamId,
 };
 
 use tokio::sync::{ Mutex };
@@ -42,20 +41,15 @@ pub struct SocketStatePeer {
     pub bi_streams: Option<Arc<Mutex<IncomingBiStreams>>>,
 }
 
-#[derive(Clone, Debug, Default)]
+// The send and receive halves are locked separately so reads and writes don't wait on each other.
+#[derive(Clone, Default)]
 pub struct StreamState {
-    pub local: Option<Arc<Mutex<StreamStateLocal>>>,
+    pub id: Option<StreamId>,
+    pub send: Option<Arc<Mutex<SendStream>>>,
+    pub recv: Option<Arc<Mutex<RecvStream>>>,
 //    pub peer: Option<Arc<StreamStatePeer>>,
 }
 
-#[derive(Default)]
-pub struct StreamStateLocal {
-    pub bi_future: Option<OpenBi>,
-    pub uni_future: Option<OpenUni>,
-    pub recv: Option<RecvStream>,
-    pub send: Option<SendStream>,
-}
-
 // Currently not used, but will be used when active receive is implemented.
 #[derive(Default)]
 pub struct StreamStatePeer;
@@ -72,9 +66,9 @@ impl fmt::Debug for SocketStatePeer {
     }
 }
 
-impl fmt::Debug for StreamStateLocal {
+impl fmt::Debug for StreamState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Local Stream State Error.")
+        write!(f, "Stream State Error.")
     }
 }
 
@@ -123,6 +117,26 @@ impl From<NewConnection> for SocketState {
     }
 }
 
+// A connection opened from the endpoint. The endpoint is kept so the connection can rebind it.
+impl From<(Endpoint, NewConnection)> for SocketState {
+    fn from((endpoint, conn): (Endpoint, NewConnection)) -> Self {
+        let local = Some(Arc::new(Mutex::new(
+            SocketStateLocal {
+                endpoint: Some(endpoint),
+                connection: Some(conn.connection),
+                ..Default::default()
+            })));
+        let peer = SocketStatePeer {
+            uni_streams: Some(Arc::new(Mutex::new(conn.uni_streams))),
+            bi_streams: Some(Arc::new(Mutex::new(conn.bi_streams))),
+        };
+        SocketState {
+            local,
+            peer,
+        }
+    }
+}
+
 impl From<Endpoint> for SocketState {
     fn from(endpoint: Endpoint) -> Self {
         SocketState {
@@ -159,56 +173,11 @@ impl From<Incoming> for SocketStateLocal {
     }
 }
 
-impl From<OpenUni> for StreamState {
-    fn from(future: OpenUni) -> Self {
-        StreamState {
-            local: Some(Arc::new(Mutex::new(future.into()))),
-            ..Default::default()
-        }
-    }
-}
-
-impl From<OpenUni> for StreamStateLocal {
-    fn from(future: OpenUni) -> Self {
-        StreamStateLocal {
-            uni_future: Some(future),
-            ..Default::default()
-        }
-    }
-}
-        
-impl From<OpenBi> for StreamState {
-    fn from(future: OpenBi) -> Self {
-        StreamState {
-            local: Some(Arc::new(Mutex::new(future.into()))),
-            ..Default::default()
-        }
-    }
-}
-
-impl From<OpenBi> for StreamStateLocal {
-    fn from(future: OpenBi) -> Self {
-        StreamStateLocal {
-            bi_future: Some(future),
-            ..Default::default()
-        }
-    }
-}
-
 impl From<SendStream> for StreamState {
     fn from(send: SendStream) -> Self {
-        let local = send.into();
         StreamState {
-            local: Some(Arc::new(Mutex::new(local))),
-            ..Default::default()
-        }
-    }
-}
-
-impl From<SendStream> for StreamStateLocal {
-    fn from(send: SendStream) -> Self {
-        StreamStateLocal {
-            send: Some(send),
+            id: Some(send.id()),
+            send: Some(Arc::new(Mutex::new(send))),
             ..Default::default()
         }
     }
@@ -216,39 +185,20 @@ impl From<SendStream> for StreamStateLocal {
 
 impl From<RecvStream> for StreamState {
     fn from(recv: RecvStream) -> Self {
-        let local = recv.into();
         StreamState {
-            local: Some(Arc::new(Mutex::new(local))),
-            ..Default::default()
-        }
-    }
-}
-
-impl From<RecvStream> for StreamStateLocal {
-    fn from(recv: RecvStream) -> Self {
-        StreamStateLocal {
-            recv: Some(recv),
+            id: Some(recv.id()),
+            recv: Some(Arc::new(Mutex::new(recv))),
             ..Default::default()
         }
     }
 }
 
 impl From<(SendStream, RecvStream)> for StreamState {
-    fn from(bi_stream : (SendStream, RecvStream)) -> Self {
-        let local = bi_stream.into();
-        Self {
-            local: Some(Arc::new(Mutex::new(local))),
-            ..Default::default()
-        }
-    }
-}
-
-impl From<(SendStream, RecvStream)> for StreamStateLocal {
     fn from((send, recv) : (SendStream, RecvStream)) -> Self {
-        Self {
-            send: Some(send),
-            recv: Some(recv),
-            ..Default::default()
+        StreamState {
+            id: Some(send.id()),
+            send: Some(Arc::new(Mutex::new(send))),
+            recv: Some(Arc::new(Mutex::new(recv))),
         }
     }
 }
@@ -3,10 +3,14 @@ defmodule CentaurusTest do
   doctest Centaurus
 
   alias Centaurus.Core
+  alias Centaurus.Nif
   alias Centaurus.Types
   alias Types.SocketConfig
   alias Types.StreamConfig
   alias Types.Options
+  alias Types.Admission
+  alias Types.Retry
+  alias Types.StreamAcceptor
   
   setup_all do
     cert_dir = :code.priv_dir(:centaurus) |> to_string
@@ -98,4 +102,663 @@ defmodule CentaurusTest do
     {:error, _error} = Core.connect(socket_config, stream_config, 0, {0,0,0,0}, %Options{}, 0)
     assert(true)
   end
+
+  test "Dropped sockets stop their runtime tasks", context do
+    {:ok, before} = Nif.live_tasks()
+    during = Task.async(fn ->
+      {_listener, _port} = listen(context)
+      {:ok, during} = Nif.live_tasks()
+      during
+    end)
+    |> Task.await()
+    assert(during > before)
+    assert(wait_for_tasks(before, 50))
+  end
+
+  test "Dropped connections close with the close code", context do
+    {listener, port} = listen(context)
+    {:ok, before} = Nif.live_tasks()
+    # Only the task holds the client's socket, so it is dropped when the task exits.
+    client = Task.async(fn ->
+      {:ok, _socket} = Task.await(connect_async(context, port, options: %Options{close_code: 42}))
+      :ok
+    end)
+    {:ok, _server} = Core.accept(listener, 10_000)
+    :ok = Task.await(client)
+    assert_receive {:quic_closed, _server, reason}, 5_000
+    assert(reason =~ "42")
+    assert(wait_for_tasks(before, 50))
+  end
+
+  test "Dropped streams are finished, or reset once the peer stops them", context do
+    %{socket: socket} = connected_pair(context)
+    {:ok, before} = Nif.live_tasks()
+    parent = self()
+    # Hands the stream to the test process and drops it when told to.
+    open = fn ->
+      Task.async(fn ->
+        {:ok, stream} = Core.open_stream(socket, :bi)
+        :ok = Core.controlling_process(stream, parent)
+        :ok = Core.write(stream, "ping")
+        receive do :drop -> :ok end
+      end)
+    end
+    finished = open.()
+    server_stream = peer_stream()
+    send(finished.pid, :drop)
+    :ok = Task.await(finished)
+    {:ok, "ping"} = Core.read(server_stream, 4, 5_000)
+    {:error, _finished} = Core.read(server_stream, 4, 5_000)
+    :ok = Core.close_stream(server_stream, 0)
+    reset = open.()
+    server_stream = peer_stream()
+    # Stopping the stream makes the dropped stream's finish fail, so it is reset instead.
+    :ok = Core.close_stream(server_stream, 0)
+    send(reset.pid, :drop)
+    :ok = Task.await(reset)
+    assert(wait_for_tasks(before, 50))
+  end
+
+  test "Sockets close when their owner exits", context do
+    %{socket: socket} = connected_pair(context)
+    {:ok, before} = Nif.live_tasks()
+    owner = spawn(fn -> receive do :stop -> :ok end end)
+    :ok = Core.controlling_process(socket, owner)
+    send(owner, :stop)
+    assert(wait_for_tasks(before - 1, 50))
+  end
+
+  test "Accepted connections and opened streams belong to the caller", context do
+    {listener, port} = listen(context)
+    client = connect_async(context, port)
+    acceptor = Task.async(fn ->
+      {:ok, server} = Core.accept(listener, 10_000)
+      assert_receive {:quic_connected, _server}, 5_000
+      server
+    end)
+    {:ok, _socket} = Task.await(client)
+    _server = Task.await(acceptor)
+    # The accepted connection closes with the process that accepted it.
+    assert_receive {:quic_closed, _socket, _reason}, 5_000
+    %{socket: socket} = connected_pair(context, stream: [stream_pid: nil])
+    {:ok, before} = Nif.live_tasks()
+    # Without a stream_pid the stream closes with the process that opened it, even while the
+    # test holds it.
+    stream = Task.async(fn ->
+      {:ok, stream} = Core.open_stream(socket, :bi)
+      stream
+    end)
+    |> Task.await()
+    assert(wait_for_tasks(before, 50))
+    {:error, _closed} = Core.write(stream, "gone")
+  end
+
+  test "Sockets and streams belong to the pid in their config", context do
+    {listener, port} = listen(context)
+    # Without a socket_pid the connection belongs to the task that connected.
+    {:ok, _socket} = Task.await(connect_async(context, port, socket_pid: nil))
+    {:ok, server} = Core.accept(listener, 10_000)
+    server_id = Core.id(server)
+    assert_receive {:quic_closed, ^server_id, _reason}, 5_000
+    # The stream_pid, the test process, owns streams opened by other processes.
+    %{socket: socket} = connected_pair(context)
+    stream = Task.async(fn ->
+      {:ok, stream} = Core.open_stream(socket, :bi)
+      stream
+    end)
+    |> Task.await()
+    Process.sleep(100)
+    :ok = Core.write(stream, "kept")
+  end
+
+  test "Listeners stop accepting when their owner exits", context do
+    {listener, _port} = listen(context)
+    {:ok, before} = Nif.live_tasks()
+    owner = spawn(fn -> receive do :stop -> :ok end end)
+    :ok = Core.controlling_process(listener, owner)
+    send(owner, :stop)
+    assert(wait_for_tasks(before - 1, 50))
+    {:error, _closed} = Core.accept(listener, 1_000)
+  end
+
+  test "Full queues return busy", context do
+    socket_config = %SocketConfig{context[:socket_config_client][:der] |
+                                  socket_pid: self(),
+                                  bind_address: "127.0.0.1:0",
+                                  options: %Options{queue_capacity: 0}}
+    stream_config = %StreamConfig{context[:stream_config_uni] | stream_pid: self()}
+    {:error, :busy} = Core.connect(socket_config, stream_config, 9001, {127,0,0,1}, %Options{}, 1_000)
+  end
+
+  test "Calls keep their place in the queue until they return", context do
+    %{socket: socket} = connected_pair(context, stream: [options: %Options{queue_capacity: 1}])
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    {:ok, read} = Core.read_async(stream, 4, :infinity)
+    Process.sleep(100)
+    # The pending read still holds the only place.
+    {:error, :busy} = Core.write(stream, "ping")
+    :ok = Core.cancel(read)
+    assert_receive {:quic_read, ^read, {:error, :cancelled}}, 5_000
+    assert_receive {:quic_ready, _stream, _id}, 5_000
+    :ok = Core.write(stream, "ping")
+  end
+
+  test "Reads and writes on a bi stream do not block each other", context do
+    %{socket: socket} = connected_pair(context)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    server_stream = peer_stream()
+    reader = Task.async(fn -> Core.read(stream, 4, 10_000) end)
+    Process.sleep(100)
+    # The read above is still waiting on the peer.
+    :ok = Core.write(stream, "more")
+    {:ok, _data} = Core.read(server_stream, 8, 10_000)
+    :ok = Core.write(server_stream, "pong")
+    assert({:ok, "pong"} == Task.await(reader, 15_000))
+  end
+
+  test "Closing a stream abandons its pending read", context do
+    %{socket: socket} = connected_pair(context)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    server_stream = peer_stream()
+    {:ok, read} = Core.read_async(stream, 4, :infinity)
+    Process.sleep(100)
+    :ok = Core.close_stream(stream, 0)
+    assert_receive {:quic_read, ^read, {:error, _closed}}, 5_000
+    # The sending half was still finished.
+    {:ok, "ping"} = Core.read(server_stream, 4, 5_000)
+    {:error, _finished} = Core.read(server_stream, 4, 5_000)
+  end
+
+  test "Split streams can be owned by different processes", context do
+    %{socket: socket} = connected_pair(context)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    {:ok, {send_half, recv_half}} = Core.split(stream)
+    parent = self()
+    reader = spawn(fn ->
+      receive do
+        :read -> send(parent, {:read, Core.read(recv_half, 4, 10_000)})
+      end
+    end)
+    :ok = Core.controlling_process(recv_half, reader)
+    send(reader, :read)
+    :ok = Core.write(send_half, "ping")
+    server_stream = peer_stream()
+    :ok = Core.write(server_stream, "pong")
+    assert_receive({:read, {:ok, "pong"}}, 10_000)
+    {:error, _} = Core.write(stream, "gone")
+  end
+
+  test "Passive sockets queue peer streams for accept_stream", context do
+    %{server: server, socket: socket} =
+      connected_pair(context, server: [stream_acceptor: %StreamAcceptor{mode: :passive}])
+    {:error, _timeout} = Core.accept_stream(server, :bi, 200)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    {:ok, server_stream} = Core.accept_stream(server, :bi, 5_000)
+    {:ok, "ping"} = Core.read(server_stream, 4, 5_000)
+    refute_received {:quic_stream, _socket, _stream, _type, _id}
+    # A call waiting on accept_stream gets the next stream as it arrives.
+    waiting = Task.async(fn ->
+      with {:ok, uni} <- Core.accept_stream(server, :uni, 5_000) do
+        Core.read(uni, 4, 5_000)
+      end
+    end)
+    {:ok, uni} = Core.open_stream(socket, :uni)
+    :ok = Core.write(uni, "pong")
+    assert({:ok, "pong"} == Task.await(waiting))
+  end
+
+  test "Active stream acceptors hand streams to their pids in turn", context do
+    parent = self()
+    [a, b] = for name <- [:a, :b], do: spawn(fn -> relay_streams(parent, name) end)
+    %{socket: socket} =
+      connected_pair(context, server: [stream_acceptor: %StreamAcceptor{bi: [a, b]}])
+    for _ <- 1..3 do
+      {:ok, stream} = Core.open_stream(socket, :bi)
+      :ok = Core.write(stream, "ping")
+    end
+    assert_receive {:a, :bi}, 5_000
+    assert_receive {:b, :bi}, 5_000
+    assert_receive {:a, :bi}, 5_000
+    refute_receive {:b, :bi}, 200
+    # Without pids for the stream type, streams go to the socket's owner.
+    {:ok, uni} = Core.open_stream(socket, :uni)
+    :ok = Core.write(uni, "ping")
+    assert_receive {:quic_stream, _socket, _stream, :uni, _id}, 5_000
+  end
+
+  test "Stream info", context do
+    %{socket: socket} = connected_pair(context)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    {:ok, %{id: 0, direction: :bi, initiator: :host, socket: info_socket}} = Core.stream_info(stream)
+    assert(info_socket != nil)
+    {:ok, uni} = Core.open_stream(socket, :uni)
+    {:ok, %{id: 2, direction: :uni, initiator: :host}} = Core.stream_info(uni)
+    :ok = Core.write(uni, "ping")
+    {server_uni, id} = receive do
+      {:quic_stream, _socket, server_uni, :uni, id} -> {server_uni, id}
+    after 10_000 -> flunk("No stream from the peer.")
+    end
+    {:ok, %{id: ^id, direction: :uni, initiator: :peer}} = Core.stream_info(server_uni)
+  end
+
+  test "Stream priorities", context do
+    %{socket: socket} = connected_pair(context, stream: [priority: 5])
+    # Streams are opened with the configured priority.
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    :ok = Core.set_priority(stream, -3)
+    :ok = Core.write(stream, "more")
+    {:ok, uni} = Core.open_stream(socket, :uni)
+    :ok = Core.set_priority(uni, 10)
+    :ok = Core.write(uni, "ping")
+    server_stream = peer_stream()
+    {:ok, _data} = Core.read(server_stream, 8, 5_000)
+    server_uni = peer_stream(:uni)
+    # Only streams that send can have a priority.
+    {:error, _receive_only} = Core.set_priority(server_uni, 1)
+  end
+
+  test "Higher priority streams are sent ahead of lower ones", context do
+    %{socket: socket} = connected_pair(context)
+    {:ok, low} = Core.open_stream(socket, :bi)
+    {:ok, high} = Core.open_stream(socket, :bi)
+    :ok = Core.set_priority(high, 10)
+    {:ok, %{id: low_id}} = Core.stream_info(low)
+    {:ok, %{id: high_id}} = Core.stream_info(high)
+    # Sending this takes many round trips, so the high priority stream's data is written
+    # while most of it is still waiting to be sent.
+    bulk = String.duplicate("a", 1_000_000)
+    :ok = Core.write(low, bulk)
+    :ok = Core.write(high, "ping")
+    streams = Map.new([peer_stream(), peer_stream()], fn stream ->
+      {:ok, %{id: id}} = Core.stream_info(stream)
+      {id, stream}
+    end)
+    high_read = Task.async(fn -> read_all(streams[high_id], 4) end)
+    low_read = Task.async(fn -> read_all(streams[low_id], byte_size(bulk)) end)
+    {"ping", high_done} = Task.await(high_read, 10_000)
+    {^bulk, low_done} = Task.await(low_read, 10_000)
+    assert(high_done < low_done)
+  end
+
+  test "Peers are followed when they rebind", context do
+    %{socket: socket} = connected_pair(context)
+    :ok = Core.rebind(socket, "127.0.0.1:0")
+    {:ok, {_ip, port}} = Core.local_address(socket)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    assert_receive({:quic_peer_address, _socket, {{127,0,0,1}, ^port}}, 5_000)
+  end
+
+  test "Peers listen and connect from the same port", context do
+    peer_config = %SocketConfig{context[:socket_config_server][:der] |
+                                socket_pid: self(),
+                                bind_address: "127.0.0.1:0"}
+    stream_config = %StreamConfig{context[:stream_config_bi] | stream_pid: self()}
+    {:ok, peer_a} = Core.peer(peer_config, stream_config)
+    {:ok, peer_b} = Core.peer(peer_config, stream_config)
+    {:ok, {_ip, port_a}} = Core.local_address(peer_a)
+    {:ok, {_ip, port_b}} = Core.local_address(peer_b)
+    a_to_b = Task.async(fn -> Core.connect_from(peer_a, port_b, {127,0,0,1}, 10_000) end)
+    {:ok, _} = Core.accept(peer_b, 10_000)
+    {:ok, _} = Task.await(a_to_b)
+    b_to_a = Task.async(fn -> Core.connect_from(peer_b, port_a, {127,0,0,1}, 10_000) end)
+    {:ok, _} = Core.accept(peer_a, 10_000)
+    {:ok, _} = Task.await(b_to_a)
+  end
+
+  test "Listen on an open socket", context do
+    {:ok, udp} = :socket.open(:inet, :dgram, :udp)
+    :ok = :socket.bind(udp, %{family: :inet, addr: {127,0,0,1}, port: 0})
+    {:ok, %{port: port}} = :socket.sockname(udp)
+    socket_config = %SocketConfig{context[:socket_config_server][:der] |
+                                  socket_pid: self(),
+                                  fd: udp,
+                                  options: %Options{recv_buffer: 65_536}}
+    stream_config = %StreamConfig{context[:stream_config_bi] | stream_pid: self()}
+    {:ok, listener} = Core.listen(socket_config, stream_config)
+    client = connect_async(context, port)
+    {:ok, _server} = Core.accept(listener, 10_000)
+    {:ok, _socket} = Task.await(client)
+  end
+
+  test "Listening on an open socket that isn't UDP fails", context do
+    {:ok, tcp} = :socket.open(:inet, :stream, :tcp)
+    socket_config = %SocketConfig{context[:socket_config_server][:der] | socket_pid: self(), fd: tcp}
+    stream_config = %StreamConfig{context[:stream_config_bi] | stream_pid: self()}
+    {:error, _not_udp} = Core.listen(socket_config, stream_config)
+  end
+
+  test "Local address reports the picked port", context do
+    {listener, port} = listen(context)
+    assert(port > 0)
+    {:ok, {{127,0,0,1}, ^port}} = Core.local_address(listener)
+    client = connect_async(context, port)
+    {:ok, server} = Core.accept(listener, 10_000)
+    {:ok, socket} = Task.await(client)
+    {:ok, {{127,0,0,1}, client_port}} = Core.local_address(socket)
+    assert(client_port > 0 and client_port != port)
+    {:ok, {{127,0,0,1}, ^port}} = Core.local_address(server)
+  end
+
+  test "Connect by hostname", context do
+    {listener, port} = listen(context)
+    client_config = %SocketConfig{context[:socket_config_client][:der] |
+                                  socket_pid: self(),
+                                  bind_address: "0.0.0.0:0"}
+    stream_config = %StreamConfig{context[:stream_config_bi] | stream_pid: self()}
+    client = Task.async(fn ->
+      Core.connect(client_config, stream_config, port, "localhost", %Options{}, 10_000)
+    end)
+    {:ok, _server} = Core.accept(listener, 10_000)
+    {:ok, _socket} = Task.await(client)
+  end
+
+  test "Connect skips addresses the socket can't reach", context do
+    {_listener, port} = listen(context)
+    client_config = %SocketConfig{context[:socket_config_client][:der] |
+                                  socket_pid: self(),
+                                  bind_address: "0.0.0.0:0"}
+    stream_config = %StreamConfig{context[:stream_config_bi] | stream_pid: self()}
+    {:error, _unreachable} =
+      Core.connect(client_config, stream_config, port, {0, 0, 0, 0, 0, 0, 0, 1}, %Options{}, 1_000)
+  end
+
+  test "Listener refuses denied addresses", context do
+    {listener, port} = listen(context, admission: %Admission{deny: ["127.0.0.0/8"]})
+    client = connect_async(context, port, [], 1_000)
+    {:error, _error} = Core.accept(listener, 2_000)
+    Task.await(client)
+    {:ok, %{accepted: 0, denied: denied}} = Core.admission_stats(listener)
+    assert(denied >= 1)
+  end
+
+  test "Listener refuses connections past max_connections", context do
+    {listener, port} = listen(context, admission: %Admission{max_connections: 1})
+    {:ok, _socket} = Task.await(connect_async(context, port))
+    {:error, _refused} = Task.await(connect_async(context, port, [], 2_000))
+    {:ok, _server} = Core.accept(listener, 1_000)
+    {:ok, %{accepted: 1, at_capacity: at_capacity}} = Core.admission_stats(listener)
+    assert(at_capacity >= 1)
+  end
+
+  test "Handshakes finish before accept is called", context do
+    {listener, port} = listen(context)
+    {:ok, _socket} = Task.await(connect_async(context, port))
+    {:ok, _server} = Core.accept(listener, 0)
+  end
+
+  test "Listener validates addresses with Retry", context do
+    token_key = Path.join(System.tmp_dir!(), "centaurus_token_key")
+    File.write!(token_key, :crypto.strong_rand_bytes(64))
+    connected_pair(context, server: [retry: %Retry{mode: :always, token_key: token_key}])
+  end
+
+  test "Retry tokens are checked before the handshake", context do
+    # Tokens that have expired by the time the client echoes them turn every client away.
+    {_listener, port} = listen(context, retry: %Retry{mode: :always, token_lifetime: 0})
+    {:error, _invalid_token} = Task.await(connect_async(context, port, [], 5_000), 10_000)
+    # Without Retry no token is asked for, so their lifetime doesn't matter.
+    connected_pair(context, server: [retry: %Retry{mode: :never, token_lifetime: 0}])
+  end
+
+  test "Configure the reset key and connection ID length", context do
+    reset_key = Path.join(System.tmp_dir!(), "centaurus_reset_key")
+    File.write!(reset_key, :crypto.strong_rand_bytes(64))
+    connected_pair(context, server: [reset_key: reset_key, cid_length: 16],
+                            client: [cid_length: 8])
+    server_config = %SocketConfig{context[:socket_config_server][:der] |
+                                  socket_pid: self(),
+                                  bind_address: "127.0.0.1:0",
+                                  cid_length: 21}
+    stream_config = %StreamConfig{context[:stream_config_bi] | stream_pid: self()}
+    {:error, _error} = Core.listen(server_config, stream_config)
+  end
+
+  test "Shut down a listener", context do
+    %{listener: listener} = connected_pair(context)
+    {:ok, 1} = Core.shutdown(listener, 5_000, {0, "Shutting down."})
+    {:error, _error} = Core.accept(listener, 1_000)
+  end
+
+  test "Shutdown lets open streams finish before closing", context do
+    %{listener: listener, server: server, socket: socket} = connected_pair(context)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    server_stream = peer_stream()
+    shutdown = Task.async(fn -> Core.shutdown(listener, 10_000, {0, "Shutting down."}) end)
+    assert_receive {:quic_goaway, _server}, 5_000
+    {:error, _going_away} = Core.open_stream(server, :bi)
+    {:ok, "ping"} = Core.read(server_stream, 4, 5_000)
+    :ok = Core.write(server_stream, "pong")
+    {:ok, "pong"} = Core.read(stream, 4, 5_000)
+    :ok = Core.close_stream(server_stream, 0)
+    assert({:ok, 1} == Task.await(shutdown, 10_000))
+  end
+
+  test "Close an endpoint and wait for it to go idle", context do
+    %{listener: listener} = connected_pair(context)
+    :ok = Core.close_endpoint(listener, 0, "Closing.")
+    :ok = Core.wait_idle(listener, 10_000)
+  end
+
+  test "Keep an open connection alive", context do
+    %{server: server, socket: socket} =
+      connected_pair(context, server: [options: %Options{idle_timeout: 1_000}],
+                              client: [options: %Options{idle_timeout: 1_000}])
+    :ok = Core.set_keep_alive(socket, 200)
+    refute_receive {:quic_idle_timeout, _socket}, 2_000
+    :ok = Core.set_keep_alive(socket, 0)
+    assert_receive {:quic_idle_timeout, _socket}, 5_000
+    # Keeps the server's socket alive until the timeout has been reported.
+    Core.close(server, 0, "")
+  end
+
+  test "Connection lifecycle messages", context do
+    %{server: server, socket: socket} = connected_pair(context)
+    server_id = Core.id(server)
+    socket_id = Core.id(socket)
+    assert(server_id != socket_id)
+    assert_receive {:quic_connected, ^server_id}, 1_000
+    assert_receive {:quic_connected, ^socket_id}, 1_000
+    :ok = Core.close(socket, 0, "Done.")
+    assert_receive {:quic_closed, ^socket_id, _reason}, 5_000
+    assert_receive {:quic_closed, ^server_id, _reason}, 5_000
+    # Keeps the server's socket alive until the peer's close has been reported.
+    Core.close(server, 0, "")
+  end
+
+  test "Unread messages don't keep a connection open", context do
+    {listener, port} = listen(context)
+    {:ok, before} = Nif.live_tasks()
+    # Only the task holds the client's socket, while its messages go to the test process.
+    client_id = Task.async(fn ->
+      {:ok, socket} = Task.await(connect_async(context, port))
+      Core.id(socket)
+    end)
+    |> Task.await()
+    {:ok, _server} = Core.accept(listener, 10_000)
+    assert_receive {:quic_closed, _server, _reason}, 5_000
+    assert_received {:quic_connected, ^client_id}
+    assert(wait_for_tasks(before, 50))
+  end
+
+  test "Idle connections time out", context do
+    %{server: server, socket: socket} =
+      connected_pair(context, server: [options: %Options{idle_timeout: 500}],
+                              client: [options: %Options{idle_timeout: 500}])
+    assert_receive {:quic_idle_timeout, _socket}, 5_000
+    assert_receive {:quic_idle_timeout, _socket}, 5_000
+    refute_received {:quic_closed, _socket, _reason}
+    # Keeps both sockets alive until their timeouts have been reported.
+    Core.close(server, 0, "")
+    Core.close(socket, 0, "")
+  end
+
+  test "Calls without a timeout use the configured one", context do
+    {listener, port} = listen(context, [options: %Options{timeout: 200}],
+                                       [options: %Options{timeout: 200}])
+    {:error, _timeout} = Core.accept(listener)
+    client = connect_async(context, port, [], 10_000, [options: %Options{timeout: 200}])
+    {:ok, _server} = Core.accept(listener, 10_000)
+    {:ok, socket} = Task.await(client)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    server_stream = peer_stream()
+    {:error, _timeout} = Core.read(stream, 4)
+    reader = Task.async(fn -> Core.read(stream, 4, :infinity) end)
+    Process.sleep(400)
+    :ok = Core.write(server_stream, "pong")
+    assert({:ok, "pong"} == Task.await(reader, 10_000))
+  end
+
+  test "Cancel a pending accept and read", context do
+    {listener, port} = listen(context)
+    {:ok, accept} = Core.accept_async(listener, :infinity)
+    :ok = Core.cancel(accept)
+    assert_receive {:quic_accept, ^accept, {:error, :cancelled}}, 5_000
+    client = connect_async(context, port)
+    {:ok, _server} = Core.accept(listener, 10_000)
+    {:ok, socket} = Task.await(client)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    {:ok, read} = Core.read_async(stream, 4, :infinity)
+    Process.sleep(100)
+    :ok = Core.cancel(read)
+    assert_receive {:quic_read, ^read, {:error, :cancelled}}, 5_000
+  end
+
+  test "Cancel an accept and a read while they wait behind another", context do
+    {listener, port} = listen(context)
+    {:ok, first_accept} = Core.accept_async(listener, :infinity)
+    {:ok, second_accept} = Core.accept_async(listener, :infinity)
+    Process.sleep(100)
+    :ok = Core.cancel(second_accept)
+    assert_receive {:quic_accept, ^second_accept, {:error, :cancelled}}, 5_000
+    {:ok, socket} = Task.await(connect_async(context, port))
+    assert_receive {:quic_accept, ^first_accept, {:ok, _server}}, 5_000
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    server_stream = peer_stream()
+    {:ok, first_read} = Core.read_async(stream, 4, :infinity)
+    {:ok, second_read} = Core.read_async(stream, 4, :infinity)
+    Process.sleep(100)
+    :ok = Core.cancel(second_read)
+    assert_receive {:quic_read, ^second_read, {:error, :cancelled}}, 5_000
+    :ok = Core.write(server_stream, "pong")
+    assert_receive {:quic_read, ^first_read, {:ok, "pong"}}, 5_000
+  end
+
+  test "Log events and metrics", context do
+    :ok = Nif.set_log_handler(self(), :info)
+    %{socket: socket} = connected_pair(context)
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    assert_receive {:centaurus_log, :info, message}, 5_000
+    assert(message =~ "connection{id=")
+    :ok = Nif.set_log_handler(nil, :info)
+    {:ok, metrics} = Core.metrics()
+    assert(metrics.connections_opened >= 2)
+    assert(metrics.bytes_written >= 4)
+  end
+
+  test "Handshake duration is timed from the handshake, not the accept", context do
+    {listener, port} = listen(context, telemetry_pid: self())
+    {:ok, _socket} = Task.await(connect_async(context, port))
+    Process.sleep(500)
+    {:ok, _server} = Core.accept(listener, 1_000)
+    assert_receive {:centaurus_telemetry, [:centaurus, :connection, :start],
+                    [handshake_duration: handshake], _metadata}, 5_000
+    assert(handshake < 500_000)
+  end
+
+  test "Telemetry events", context do
+    %{server: server, socket: socket} = connected_pair(context, server: [telemetry_pid: self()])
+    {:ok, {_ip, client_port}} = Core.local_address(socket)
+    assert_receive {:centaurus_telemetry, [:centaurus, :connection, :start],
+                    [handshake_duration: _], %{peer: {_, ^client_port}}}, 5_000
+    {:ok, stream} = Core.open_stream(socket, :bi)
+    :ok = Core.write(stream, "ping")
+    assert_receive {:centaurus_telemetry, [:centaurus, :stream, :open], [],
+                    %{direction: :bi, initiator: :peer}}, 5_000
+    :ok = Core.close(socket, 0, "Done.")
+    assert_receive {:centaurus_telemetry, [:centaurus, :connection, :stop],
+                    [duration: _], %{reason: reason}}, 5_000
+    assert(is_binary(reason))
+    Core.close(server, 0, "")
+  end
+
+  # Listens on a port picked by the system. Returns the listener and its port.
+  defp listen(context, server \\ [], stream \\ []) do
+    server_config = %SocketConfig{context[:socket_config_server][:der] |
+                                  socket_pid: self(),
+                                  bind_address: "127.0.0.1:0"}
+    stream_config = %StreamConfig{context[:stream_config_bi] | stream_pid: self()}
+    {:ok, listener} = Core.listen(struct(server_config, server), struct(stream_config, stream))
+    {:ok, {_ip, port}} = Core.local_address(listener)
+    {listener, port}
+  end
+
+  # Connects to the port from a task, so the caller can accept or wait on other messages meanwhile.
+  defp connect_async(context, port, client \\ [], timeout \\ 10_000, stream \\ []) do
+    client_config = %SocketConfig{context[:socket_config_client][:der] |
+                                  socket_pid: self(),
+                                  bind_address: "127.0.0.1:0"}
+    client_config = struct(client_config, client)
+    stream_config = struct(%StreamConfig{context[:stream_config_bi] | stream_pid: self()}, stream)
+    Task.async(fn ->
+      Core.connect(client_config, stream_config, port, {127,0,0,1}, %Options{}, timeout)
+    end)
+  end
+
+  # A listener, a connection to it and the listener's side of the connection. The :server,
+  # :client and :stream options override fields of the configs.
+  defp connected_pair(context, opts \\ []) do
+    stream = Keyword.get(opts, :stream, [])
+    {listener, port} = listen(context, Keyword.get(opts, :server, []), stream)
+    client = connect_async(context, port, Keyword.get(opts, :client, []), 10_000, stream)
+    {:ok, server} = Core.accept(listener, 10_000)
+    {:ok, socket} = Task.await(client)
+    %{listener: listener, server: server, socket: socket}
+  end
+
+  # Tells the parent which streams reached the process named name.
+  defp relay_streams(parent, name) do
+    receive do
+      {:quic_stream, _socket, _stream, type, _id} ->
+        send(parent, {name, type})
+        relay_streams(parent, name)
+    end
+  end
+
+  # The next stream of the type the peer opened.
+  defp peer_stream(type \\ :bi) do
+    receive do
+      {:quic_stream, _socket, stream, ^type, _id} -> stream
+    after 10_000 -> flunk("No stream from the peer.")
+    end
+  end
+
+  # Reads until size bytes have arrived. Returns them with the time the last arrived.
+  defp read_all(stream, size, data \\ "")
+  defp read_all(_stream, size, data) when byte_size(data) >= size do
+    {data, System.monotonic_time()}
+  end
+  defp read_all(stream, size, data) do
+    {:ok, more} = Core.read(stream, size - byte_size(data), 10_000)
+    read_all(stream, size, data <> more)
+  end
+
+  defp wait_for_tasks(_count, 0), do: false
+  defp wait_for_tasks(count, retries) do
+    :erlang.garbage_collect()
+    case Nif.live_tasks() do
+      {:ok, live} when live <= count -> true
+      _ ->
+        Process.sleep(100)
+        wait_for_tasks(count, retries - 1)
+    end
+  end
 end
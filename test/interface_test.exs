@@ -27,7 +27,14 @@ defmodule InterfaceTest do
   end
 
   test "SocketConfig", context do
-    assert({:ok, context[:socket_config]} == Nif.test_socket_config(context[:socket_config]))
+    # Addresses come back as inet tuples.
+    socket_config = %SocketConfig{context[:socket_config] | bind_address: {{127,0,0,1}, 0}}
+    assert({:ok, socket_config} == Nif.test_socket_config(context[:socket_config]))
+  end
+
+  test "SocketConfig with a tuple address", context do
+    socket_config = %SocketConfig{context[:socket_config] | bind_address: {{127,0,0,1}, 4433}}
+    assert({:ok, socket_config} == Nif.test_socket_config(socket_config))
   end
 
   test "StreamConfig", context do
@@ -67,13 +74,16 @@ defmodule InterfaceTest do
   end
 
   test "Socket Addr" do
-    addr = "127.0.0.1:0"
-    assert({:ok, addr} == Nif.test_socket_addr(addr))
+    assert({:ok, {{127,0,0,1}, 0}} == Nif.test_socket_addr("127.0.0.1:0"))
+    assert({:ok, {{127,0,0,1}, 4433}} == Nif.test_socket_addr({{127,0,0,1}, 4433}))
+    assert({:ok, {{0,0,0,0,0,0,0,1}, 4433}} == Nif.test_socket_addr({{0,0,0,0,0,0,0,1}, 4433}))
+    assert({:error, _} = Nif.test_socket_addr({:local, "/tmp/centaurus"}))
   end
 
   test "Socket Type" do
     assert({:ok, :server} == Nif.test_socket_type(:server))
     assert({:ok, :client} == Nif.test_socket_type(:client))
+    assert({:ok, :peer} == Nif.test_socket_type(:peer))
   end
   
   test "Stream Type" do